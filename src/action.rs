@@ -0,0 +1,174 @@
+use crate::events::{self, Event};
+use crate::history;
+use crate::hooks;
+use crate::taskbar;
+use eyre::{Result, bail};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+use tracing::field::Empty;
+
+/// Held while [`Action::apply`] reads and changes the taskbar.
+static APPLYING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Toggle,
+    Enable,
+    Disable,
+}
+
+impl Action {
+    pub const ALL: [Action; 3] = [Action::Toggle, Action::Enable, Action::Disable];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "toggle" => Ok(Action::Toggle),
+            "enable" | "hide" | "on" => Ok(Action::Enable),
+            "disable" | "show" | "off" => Ok(Action::Disable),
+            other => bail!(
+                "Unknown action '{other}', expected one of: {}",
+                Action::ALL.map(Action::name).join(", ")
+            ),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Toggle => "toggle",
+            Action::Enable => "enable",
+            Action::Disable => "disable",
+        }
+    }
+
+    /// The action that puts auto-hide into `enabled`.
+    pub fn for_state(enabled: bool) -> Self {
+        if enabled {
+            Action::Enable
+        } else {
+            Action::Disable
+        }
+    }
+
+    /// Runs the action against the taskbar and returns the resulting auto-hide state.
+    ///
+    /// Runs inside an `action` span carrying the action, its source, the previous and new state
+    /// and how long it took, so structured log output has them on every change. Each action is
+    /// also appended to the local event history read by `tb stats`, and each state change to the
+    /// undo history unless it is itself an undo. State changes and failures run the configured
+    /// [`hooks`].
+    pub fn perform(self, source: Source) -> Result<bool> {
+        let span = tracing::info_span!(
+            "action",
+            action = self.name(),
+            source = source.name(),
+            previous = Empty,
+            new = Empty,
+            duration_ms = Empty,
+        );
+        let _entered = span.enter();
+        let started = Instant::now();
+
+        let (previous, enabled) = match self.apply() {
+            Ok(states) => states,
+            Err(error) => {
+                hooks::failed(self, source, &error);
+                return Err(error);
+            }
+        };
+        span.record("previous", previous);
+        span.record("new", enabled);
+        span.record(
+            "duration_ms",
+            u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        );
+
+        tracing::info!(
+            "Taskbar auto-hide {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        let event = Event::new(self, source, previous, enabled);
+        if let Err(error) = events::record(&event) {
+            tracing::warn!("Failed to record the change in the event history: {error:#}");
+        }
+        let transition = event.is_transition();
+        if transition
+            && source != Source::Undo
+            && let Err(error) = history::push(event)
+        {
+            tracing::warn!("Failed to record the change in the undo history: {error:#}");
+        }
+        if transition {
+            hooks::changed(self, source, previous, enabled);
+        }
+        Ok(enabled)
+    }
+
+    /// Changes the taskbar and returns the auto-hide state before and after.
+    ///
+    /// Runs one at a time within the process, so two control connections toggling at once
+    /// cannot both read the same state and cancel each other out.
+    fn apply(self) -> Result<(bool, bool)> {
+        let _applying = APPLYING.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = taskbar::is_taskbar_auto_hide_enabled()?;
+        let enabled = taskbar::set_taskbar_auto_hide(match self {
+            Action::Toggle => !previous,
+            Action::Enable => true,
+            Action::Disable => false,
+        })?;
+        Ok((previous, enabled))
+    }
+}
+
+/// What asked for a taskbar change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Hotkey,
+    Mouse,
+    Menu,
+    Cli,
+    /// `startup.auto_hide` applied when the tray starts.
+    Startup,
+    /// A policy such as `policies.enforce` or `policies.restore_on_exit`.
+    Rule,
+    /// `tb undo` or the tray's "Undo last change".
+    Undo,
+    /// An external controller using `tb serve`.
+    Api,
+}
+
+impl Source {
+    pub const ALL: [Source; 8] = [
+        Source::Hotkey,
+        Source::Mouse,
+        Source::Menu,
+        Source::Cli,
+        Source::Startup,
+        Source::Rule,
+        Source::Undo,
+        Source::Api,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Source::ALL.into_iter().find(|source| source.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Hotkey => "hotkey",
+            Source::Mouse => "mouse",
+            Source::Menu => "menu",
+            Source::Cli => "cli",
+            Source::Startup => "startup",
+            Source::Rule => "rule",
+            Source::Undo => "undo",
+            Source::Api => "api",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
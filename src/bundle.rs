@@ -0,0 +1,463 @@
+use crate::config::{self, BindingConfig, Config};
+use crate::keymap::Binding;
+use crate::settings::{self, SCALAR_KEYS, Value};
+use crate::store;
+use eyre::{Context, Result, bail, eyre};
+use facet::Facet;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::ZipArchive;
+use zip::write::SimpleFileOptions;
+
+const BUNDLE_FORMAT: &str = "tb-config-bundle";
+const BUNDLE_VERSION: u32 = 1;
+/// Archive entry describing the bundle; read first so foreign or newer archives are rejected.
+const MANIFEST_ENTRY: &str = "manifest.json";
+const CONFIG_ENTRY: &str = "config.toml";
+/// Archive directory holding the snapshots, one entry per file.
+const SNAPSHOT_ENTRY_PREFIX: &str = "snapshots/";
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct Manifest {
+    format: String,
+    version: u32,
+    /// Version of the tb build that wrote the bundle.
+    tb_version: String,
+}
+
+/// Everything needed to provision tb on another machine, written as one zip archive holding a
+/// manifest, `config.toml` and its snapshots.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    pub tb_version: String,
+    /// `config.toml` exactly as written, comments included.
+    pub config: String,
+    pub snapshots: Vec<BundleSnapshot>,
+}
+
+#[derive(Debug, Default)]
+pub struct BundleSnapshot {
+    pub name: String,
+    pub contents: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Settings and bindings in the bundle override local ones; everything else is kept.
+    Merge,
+    /// The bundle's config file replaces the local one.
+    Replace,
+}
+
+/// One setting that an import would change.
+#[derive(Debug)]
+pub struct Change {
+    pub key: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub changes: Vec<Change>,
+    /// Snapshots in the bundle that are not present locally.
+    pub new_snapshots: Vec<String>,
+    contents: String,
+    snapshots: Vec<BundleSnapshot>,
+}
+
+pub fn export_bundle(path: &Path, tb_version: &str) -> Result<Bundle> {
+    let (_, raw) = config::read_config_file()?;
+    let config_path = config::config_path()?;
+    let snapshot_dir = config::snapshot_dir()?;
+
+    let mut snapshots = Vec::new();
+    for snapshot in store::snapshots(&config_path, &snapshot_dir)? {
+        snapshots.push(BundleSnapshot {
+            name: snapshot
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            contents: std::fs::read_to_string(&snapshot)
+                .wrap_err_with(|| format!("Failed to read snapshot {}", snapshot.display()))?,
+        });
+    }
+
+    let bundle = Bundle {
+        tb_version: tb_version.to_string(),
+        config: raw.unwrap_or_else(|| format!("version = {}\n", config::CONFIG_VERSION)),
+        snapshots,
+    };
+    let archive = write_archive(&bundle)
+        .wrap_err_with(|| format!("Failed to build bundle {}", path.display()))?;
+    store::write_atomic(path, &archive)?;
+    Ok(bundle)
+}
+
+fn write_archive(bundle: &Bundle) -> Result<Vec<u8>> {
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        tb_version: bundle.tb_version.clone(),
+    };
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    zip.start_file(MANIFEST_ENTRY, options)?;
+    zip.write_all(facet_json::to_string(&manifest).as_bytes())?;
+    zip.start_file(CONFIG_ENTRY, options)?;
+    zip.write_all(bundle.config.as_bytes())?;
+    for snapshot in &bundle.snapshots {
+        zip.start_file(format!("{SNAPSHOT_ENTRY_PREFIX}{}", snapshot.name), options)?;
+        zip.write_all(snapshot.contents.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+pub fn read_bundle(path: &Path) -> Result<Bundle> {
+    let file =
+        File::open(path).wrap_err_with(|| format!("Failed to read bundle {}", path.display()))?;
+    let mut archive = ZipArchive::new(file)
+        .wrap_err_with(|| format!("{} is not a tb config bundle", path.display()))?;
+
+    let manifest: Manifest = facet_json::from_str(&read_entry(&mut archive, MANIFEST_ENTRY)?)
+        .map_err(|error| eyre!("{}: {MANIFEST_ENTRY}: {error}", path.display()))?;
+    if manifest.format != BUNDLE_FORMAT {
+        bail!("{} is not a tb config bundle", path.display())
+    }
+    if manifest.version > BUNDLE_VERSION {
+        bail!(
+            "{}: bundle version {} was written by a newer tb (this build supports {BUNDLE_VERSION})",
+            path.display(),
+            manifest.version
+        )
+    }
+
+    let config = read_entry(&mut archive, CONFIG_ENTRY)?;
+    let names = archive
+        .file_names()
+        .filter_map(|name| name.strip_prefix(SNAPSHOT_ENTRY_PREFIX))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let mut snapshots = Vec::new();
+    for name in names {
+        let contents = read_entry(&mut archive, &format!("{SNAPSHOT_ENTRY_PREFIX}{name}"))?;
+        snapshots.push(BundleSnapshot { name, contents });
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Bundle {
+        tb_version: manifest.tb_version,
+        config,
+        snapshots,
+    })
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String> {
+    let mut contents = String::new();
+    archive
+        .by_name(name)
+        .wrap_err_with(|| format!("The bundle has no {name}"))?
+        .read_to_string(&mut contents)
+        .wrap_err_with(|| format!("Failed to read {name} from the bundle"))?;
+    Ok(contents)
+}
+
+/// Works out what importing `bundle` would change without touching anything.
+pub fn plan_import(bundle: Bundle, mode: ImportMode) -> Result<ImportPlan> {
+    let bundle_path = Path::new("<bundle>");
+    let incoming = Config::parse(&bundle.config, bundle_path)
+        .wrap_err("The bundle contains an invalid config")?;
+    let (current, raw) = config::read_config_file()?;
+
+    let contents = match mode {
+        ImportMode::Replace => bundle.config,
+        ImportMode::Merge => merge(
+            &current,
+            raw.unwrap_or_else(|| format!("version = {}\n", config::CONFIG_VERSION)),
+            &incoming,
+            &bundle.config,
+        )?,
+    };
+    let after = Config::parse(&contents, bundle_path)?;
+
+    let mut keys = settings::keys(&current);
+    for key in settings::keys(&after) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    let mut changes = Vec::new();
+    for key in keys {
+        let before = settings::get(&current, &key).ok().flatten();
+        let after = settings::get(&after, &key).ok().flatten();
+        if before != after {
+            changes.push(Change { key, before, after });
+        }
+    }
+
+    let snapshot_dir = config::snapshot_dir()?;
+    let snapshots = bundle
+        .snapshots
+        .into_iter()
+        .filter(|snapshot| is_snapshot_name(&snapshot.name))
+        .filter(|snapshot| !snapshot_dir.join(&snapshot.name).exists())
+        .collect::<Vec<_>>();
+
+    Ok(ImportPlan {
+        changes,
+        new_snapshots: snapshots
+            .iter()
+            .map(|snapshot| snapshot.name.clone())
+            .collect(),
+        contents,
+        snapshots,
+    })
+}
+
+pub fn apply_import(plan: &ImportPlan) -> Result<()> {
+    let lock = config::lock_config()?;
+    let snapshot_dir = config::snapshot_dir()?;
+    if !plan.snapshots.is_empty() {
+        std::fs::create_dir_all(&snapshot_dir)
+            .wrap_err_with(|| format!("Failed to create {}", snapshot_dir.display()))?;
+    }
+    for snapshot in &plan.snapshots {
+        store::write_atomic(
+            &snapshot_dir.join(&snapshot.name),
+            snapshot.contents.as_bytes(),
+        )?;
+    }
+    config::write_config(&plan.contents, &lock)
+}
+
+/// Applies the bundle's settings and bindings to the local `config.toml` source line by line, so
+/// the comments and layout of the local file survive.
+fn merge(
+    current: &Config,
+    mut source: String,
+    incoming: &Config,
+    incoming_source: &str,
+) -> Result<String> {
+    for key in SCALAR_KEYS {
+        let (table, field) = settings::location(key)?;
+        if config::has_key(incoming_source, table, field)
+            && let Some(value) = settings::get(incoming, key)?
+        {
+            source = config::edit_toml(&source, table, field, Some(&value.to_toml()));
+        }
+    }
+
+    if !incoming_source.contains("[[bindings]]") {
+        return Ok(source);
+    }
+    // Default bindings are not in the file, so write them out before merging into them.
+    let bindings = &current.bindings;
+    if !source.contains("[[bindings]]") {
+        for binding in bindings {
+            source = config::append_binding(&source, binding);
+        }
+    }
+    let mut identities = bindings.iter().map(identity).collect::<Result<Vec<_>>>()?;
+    for binding in &incoming.bindings {
+        let incoming_identity = identity(binding)?;
+        let existing = identities
+            .iter()
+            .position(|existing| *existing == incoming_identity);
+        if let Some(index) = existing {
+            let action = Value::Text(binding.action.clone()).to_toml();
+            source = config::edit_toml(&source, Some(("bindings", index)), "action", Some(&action));
+        } else {
+            source = config::append_binding(&source, binding);
+            identities.push(incoming_identity);
+        }
+    }
+    Ok(source)
+}
+
+/// The trigger and condition of `binding` as they print once parsed, so `^+b` in a bundle
+/// matches `Ctrl+Shift+B` in the local file.
+fn identity(binding: &BindingConfig) -> Result<(String, Option<String>)> {
+    let binding = Binding::from_config(binding)?;
+    Ok((
+        binding.trigger.to_string(),
+        binding.condition.as_ref().map(ToString::to_string),
+    ))
+}
+
+/// Rejects names that could escape the snapshot directory.
+fn is_snapshot_name(name: &str) -> bool {
+    name.starts_with("config-")
+        && Path::new(name)
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        && !name.contains(['/', '\\'])
+        && !name.contains("..")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths;
+
+    const LOCAL: &str = "# my settings\n\
+                         version = 1\n\
+                         \n\
+                         [logging]\n\
+                         level = \"debug\"\n\
+                         \n\
+                         [[bindings]]\n\
+                         # the everyday one\n\
+                         trigger = \"Ctrl+Shift+B\"\n\
+                         action = \"toggle\"\n";
+
+    const INCOMING: &str = "version = 1\n\
+                            \n\
+                            [logging]\n\
+                            level = \"warn\"\n\
+                            \n\
+                            [[bindings]]\n\
+                            trigger = \"^+b\"\n\
+                            action = \"disable\"\n\
+                            \n\
+                            [[bindings]]\n\
+                            trigger = \"Ctrl+Alt+T\"\n\
+                            action = \"enable\"\n";
+
+    fn binding(trigger: &str, action: &str) -> BindingConfig {
+        BindingConfig {
+            trigger: trigger.to_string(),
+            action: action.to_string(),
+            condition: None,
+        }
+    }
+
+    fn write_local(contents: &str) -> std::path::PathBuf {
+        paths::app_home().unwrap().ensure_dir().unwrap();
+        let path = config::config_path().unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn bundle(config: &str) -> Bundle {
+        Bundle {
+            tb_version: "test".to_string(),
+            config: config.to_string(),
+            snapshots: vec![
+                BundleSnapshot {
+                    name: "config-1.toml".to_string(),
+                    contents: LOCAL.to_string(),
+                },
+                BundleSnapshot {
+                    name: "../config-2.toml".to_string(),
+                    contents: LOCAL.to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn merge_matches_bindings_by_their_parsed_trigger() {
+        let current = Config {
+            bindings: vec![binding("Ctrl+Shift+B", "toggle")],
+            ..Config::default()
+        };
+        let incoming = Config {
+            bindings: vec![binding("^+b", "disable"), binding("Ctrl+Alt+T", "enable")],
+            logging: config::LoggingConfig {
+                level: "warn".to_string(),
+                ..config::LoggingConfig::default()
+            },
+            ..Config::default()
+        };
+
+        let merged = merge(&current, LOCAL.to_string(), &incoming, INCOMING).unwrap();
+        assert_eq!(
+            merged,
+            LOCAL
+                .replace("\"debug\"", "\"warn\"")
+                .replace("\"toggle\"", "\"disable\"")
+                + "\n[[bindings]]\ntrigger = \"Ctrl+Alt+T\"\naction = \"enable\"\n"
+        );
+    }
+
+    #[test]
+    fn merge_writes_out_default_bindings_first() {
+        let local = "version = 1\n";
+        let incoming = Config {
+            bindings: vec![binding("Ctrl+Alt+T", "enable")],
+            ..Config::default()
+        };
+        let incoming_source = "[[bindings]]\ntrigger = \"Ctrl+Alt+T\"\naction = \"enable\"\n";
+
+        let current = Config::default();
+        let merged = merge(&current, local.to_string(), &incoming, incoming_source).unwrap();
+        assert_eq!(
+            merged,
+            "version = 1\n\n[[bindings]]\ntrigger = \"Ctrl+Shift+B\"\naction = \"toggle\"\n\n\
+             [[bindings]]\ntrigger = \"Ctrl+Alt+T\"\naction = \"enable\"\n"
+        );
+    }
+
+    #[test]
+    fn plan_import_is_a_dry_run() {
+        let _dirs = paths::scratch_dirs();
+        let path = write_local(LOCAL);
+
+        let plan = plan_import(bundle(INCOMING), ImportMode::Merge).unwrap();
+        let changes = plan
+            .changes
+            .iter()
+            .map(|change| (change.key.as_str(), change.after.clone()))
+            .collect::<Vec<_>>();
+        let text = |value: &str| Some(Value::Text(value.to_string()));
+        assert_eq!(
+            changes,
+            [
+                ("logging.level", text("warn")),
+                ("bindings.0.action", text("disable")),
+                ("bindings.1.trigger", text("Ctrl+Alt+T")),
+                ("bindings.1.action", text("enable")),
+            ]
+        );
+        assert_eq!(plan.new_snapshots, ["config-1.toml"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), LOCAL);
+        assert!(!config::snapshot_dir().unwrap().exists());
+
+        apply_import(&plan).unwrap();
+        let merged = std::fs::read_to_string(&path).unwrap();
+        assert!(merged.starts_with("# my settings\n"), "{merged}");
+        let config = config::load_config_file().unwrap();
+        assert_eq!(config.logging.level, "warn");
+        assert_eq!(
+            config.bindings,
+            [
+                binding("Ctrl+Shift+B", "disable"),
+                binding("Ctrl+Alt+T", "enable")
+            ]
+        );
+        let snapshot_dir = config::snapshot_dir().unwrap();
+        assert!(snapshot_dir.join("config-1.toml").exists());
+        assert!(!snapshot_dir.join("../config-2.toml").exists());
+    }
+
+    #[test]
+    fn replace_swaps_the_whole_file() {
+        let _dirs = paths::scratch_dirs();
+        let path = write_local(LOCAL);
+        let incoming = "version = 1\n\n[cache]\nretention_days = 3\n";
+
+        let plan = plan_import(bundle(incoming), ImportMode::Replace).unwrap();
+        let keys = plan
+            .changes
+            .iter()
+            .map(|change| change.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["logging.level", "cache.retention_days"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), LOCAL);
+
+        apply_import(&plan).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), incoming);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::logfile;
+use crate::paths;
+use eyre::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Default)]
+pub struct CleanReport {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files that could not be removed, typically because another tb process has them open.
+    pub failed: usize,
+}
+
+/// Parses an age such as `7d`, `12h`, `30m` or `45s`.
+pub fn parse_age(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let Ok(amount) = amount.parse::<u64>() else {
+        bail!("Invalid age '{value}', expected a number and unit such as '7d' or '12h'")
+    };
+    let seconds = match unit.trim().to_ascii_lowercase().as_str() {
+        "d" | "day" | "days" => amount.saturating_mul(SECONDS_PER_DAY),
+        "h" | "hour" | "hours" => amount.saturating_mul(60 * 60),
+        "m" | "min" | "mins" | "minutes" => amount.saturating_mul(60),
+        "s" | "" => amount,
+        other => bail!("Unknown age unit '{other}', expected d, h, m or s"),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Total size of the cache directory in bytes; 0 when it does not exist yet.
+pub fn cache_size() -> Result<u64> {
+    Ok(paths::size_of(paths::cache_home()?.path()))
+}
+
+/// Removes cache files last modified more than `older_than` ago, or every file when `None`.
+///
+/// The active log file and the event history are kept unless `all` is set. Files that cannot be
+/// removed are counted and skipped rather than aborting the clean.
+pub fn clean(older_than: Option<Duration>, all: bool) -> Result<CleanReport> {
+    let cache = paths::cache_home()?;
+    let mut report = CleanReport::default();
+    if !cache.path().exists() {
+        return Ok(report);
+    }
+    let cutoff = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+    let keep = if all {
+        Vec::new()
+    } else {
+        vec![logfile::log_path()?, paths::events_path()?]
+    };
+    clean_dir(cache.path(), cutoff, &keep, &mut report)
+        .wrap_err_with(|| format!("Failed to clean {}", cache.path().display()))?;
+    Ok(report)
+}
+
+/// Applies `cache.retention_days`; 0 disables retention.
+#[cfg(windows)]
+pub fn enforce_retention(retention_days: u32) -> Result<CleanReport> {
+    if retention_days == 0 {
+        return Ok(CleanReport::default());
+    }
+    let age = Duration::from_secs(u64::from(retention_days) * SECONDS_PER_DAY);
+    clean(Some(age), false)
+}
+
+fn clean_dir(
+    dir: &Path,
+    cutoff: Option<SystemTime>,
+    keep: &[PathBuf],
+    report: &mut CleanReport,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            clean_dir(&path, cutoff, keep, report)?;
+            // Only succeeds once the directory is empty, which is all we want.
+            let _ = std::fs::remove_dir(&path);
+            continue;
+        }
+
+        if keep.contains(&path) || !is_expired(metadata.modified().ok(), cutoff) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                report.files += 1;
+                report.bytes += metadata.len();
+            }
+            Err(error) => {
+                tracing::debug!("Could not remove {}: {error}", path.display());
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a file last modified at `modified` is due for removal. Without a cutoff everything
+/// is; with one, files whose modification time is unknown are kept.
+fn is_expired(modified: Option<SystemTime>, cutoff: Option<SystemTime>) -> bool {
+    match (cutoff, modified) {
+        (None, _) => true,
+        (Some(cutoff), Some(modified)) => modified < cutoff,
+        (Some(_), None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn parse_ages() {
+        let cases = [
+            ("7d", 7 * SECONDS_PER_DAY),
+            ("1 day", SECONDS_PER_DAY),
+            ("2days", 2 * SECONDS_PER_DAY),
+            ("12h", 12 * 60 * 60),
+            ("12H", 12 * 60 * 60),
+            ("30m", 30 * 60),
+            ("5 mins", 5 * 60),
+            ("45s", 45),
+            ("45", 45),
+            (" 3d ", 3 * SECONDS_PER_DAY),
+            ("0d", 0),
+        ];
+        for (value, seconds) in cases {
+            assert_eq!(
+                parse_age(value).unwrap(),
+                Duration::from_secs(seconds),
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_age_rejects_malformed_input() {
+        for value in [
+            "",
+            "d",
+            "7w",
+            "-1d",
+            "1.5d",
+            "7d ago",
+            "99999999999999999999d",
+        ] {
+            assert!(parse_age(value).is_err(), "{value:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn parse_age_saturates() {
+        let age = parse_age(&format!("{}d", u64::MAX)).unwrap();
+        assert_eq!(age, Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn expiry_against_the_cutoff() {
+        let now = SystemTime::now();
+        let hour = Duration::from_hours(1);
+        let cutoff = Some(now - hour);
+        assert!(is_expired(Some(now - 2 * hour), cutoff));
+        assert!(!is_expired(Some(now - hour), cutoff));
+        assert!(!is_expired(Some(now), cutoff));
+        assert!(!is_expired(None, cutoff));
+        assert!(is_expired(Some(now), None));
+        assert!(is_expired(None, None));
+    }
+
+    #[test]
+    fn clean_dir_keeps_recent_and_protected_files() {
+        let dir = std::env::temp_dir().join(format!("tb-cache-clean-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("logs")).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(10 * SECONDS_PER_DAY);
+        let files = [
+            ("logs/tb.log", old),
+            ("logs/tb-1.log", old),
+            ("logs/tb-2.log", SystemTime::now()),
+            ("events.jsonl", old),
+        ];
+        for (name, modified) in files {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+
+        let keep = [dir.join("logs").join("tb.log"), dir.join("events.jsonl")];
+        let cutoff = SystemTime::now().checked_sub(Duration::from_secs(7 * SECONDS_PER_DAY));
+        let mut report = CleanReport::default();
+        clean_dir(&dir, cutoff, &keep, &mut report).unwrap();
+
+        assert_eq!(report.files, 1);
+        assert!(!dir.join("logs/tb-1.log").exists());
+        assert!(dir.join("logs/tb-2.log").exists());
+        assert!(dir.join("logs/tb.log").exists());
+        assert!(dir.join("events.jsonl").exists());
+
+        let mut report = CleanReport::default();
+        clean_dir(&dir, None, &[], &mut report).unwrap();
+        assert_eq!(report.files, 3);
+        assert!(!dir.join("logs").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
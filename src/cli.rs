@@ -17,6 +17,22 @@ pub struct Cli {
 pub struct GlobalArgs {
     #[facet(args::named, default)]
     pub debug: bool,
+
+    /// Log output format: `text`, `json` or `compact`.
+    #[facet(args::named, default)]
+    pub log_format: Option<String>,
+
+    /// Keep config and cache beside the executable instead of the user profile.
+    #[facet(args::named, default)]
+    pub portable: bool,
+
+    /// When starting the tray, ask an already running one to exit and take over from it.
+    #[facet(args::named, default)]
+    pub replace: bool,
+
+    /// Overrides a setting for this run, as `key=value`; may be repeated.
+    #[facet(args::named, default)]
+    pub set: Vec<String>,
 }
 
 #[derive(Facet, Debug, Default)]
@@ -27,8 +43,26 @@ pub enum Command {
     Toggle,
     Status,
     Home,
-    Cache,
+    Cache(CacheArgs),
     Hotkey(HotkeyArgs),
+    Config(ConfigArgs),
+    Schema(SchemaArgs),
+    Paths(PathsArgs),
+    Logs(LogsArgs),
+    Doctor(DoctorArgs),
+    Stats(StatsArgs),
+    /// List recent taskbar changes, newest first.
+    History,
+    /// Revert the most recent taskbar change.
+    Undo,
+    /// Ask the running tray to exit.
+    Stop,
+    /// Stop the running tray and start a new one with the current config.
+    Restart,
+    /// Show the running tray's PID, uptime, version and bindings.
+    TrayStatus,
+    /// Let another program control tb.
+    Serve(ServeArgs),
 }
 
 #[derive(Facet, Debug)]
@@ -46,4 +80,172 @@ pub enum HotkeyCommand {
         #[facet(args::positional)]
         expression: String,
     },
+    Import {
+        #[facet(args::named)]
+        from: String,
+        #[facet(args::positional)]
+        file: String,
+    },
+}
+
+#[derive(Facet, Debug)]
+pub struct ConfigArgs {
+    #[facet(args::subcommand, default)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Facet, Debug, Default)]
+#[repr(u8)]
+pub enum ConfigCommand {
+    #[default]
+    List,
+    Get {
+        #[facet(args::positional)]
+        key: String,
+    },
+    Set {
+        #[facet(args::positional)]
+        key: String,
+        #[facet(args::positional)]
+        value: String,
+    },
+    Unset {
+        #[facet(args::positional)]
+        key: String,
+    },
+    Explain {
+        #[facet(args::positional)]
+        key: String,
+    },
+    /// Write settings, bindings and snapshots to a bundle file.
+    Export {
+        #[facet(args::positional)]
+        file: String,
+    },
+    /// Apply a bundle written by `tb config export`.
+    Import {
+        #[facet(args::positional)]
+        file: String,
+        /// Override only what the bundle sets and keep everything else (default).
+        #[facet(args::named, default)]
+        merge: bool,
+        /// Replace the config file with the bundle's.
+        #[facet(args::named, default)]
+        replace: bool,
+        /// Show what would change without writing anything.
+        #[facet(args::named, default)]
+        dry_run: bool,
+    },
+    Edit,
+    Validate,
+    Path,
+}
+
+#[derive(Facet, Debug)]
+pub struct CacheArgs {
+    #[facet(args::subcommand, default)]
+    pub command: CacheCommand,
+}
+
+#[derive(Facet, Debug, Default)]
+#[repr(u8)]
+pub enum CacheCommand {
+    /// Print the cache directory.
+    #[default]
+    Path,
+    /// Print how much space the cache uses.
+    Size,
+    /// Delete cache files, optionally only those older than an age such as `7d` or `12h`.
+    Clean {
+        #[facet(args::named, default)]
+        older_than: Option<String>,
+        /// Also delete the active log file and the event history.
+        #[facet(args::named, default)]
+        all: bool,
+    },
+}
+
+#[derive(Facet, Debug)]
+pub struct LogsArgs {
+    #[facet(args::subcommand, default)]
+    pub command: LogsCommand,
+}
+
+#[derive(Facet, Debug, Default)]
+#[repr(u8)]
+pub enum LogsCommand {
+    /// Print the active log file.
+    #[default]
+    Path,
+    /// Print the last lines of the log (50 by default).
+    Tail {
+        #[facet(args::named, default)]
+        lines: Option<usize>,
+    },
+    /// Print the last lines of the log, then new output as it is written.
+    Follow {
+        #[facet(args::named, default)]
+        lines: Option<usize>,
+    },
+    /// Change the log level of the running tray, e.g. `debug` or `info,tb::tray=trace`.
+    Level {
+        #[facet(args::positional)]
+        filter: String,
+    },
+    /// Delete rotated log files and empty the active one.
+    Clear,
+}
+
+#[derive(Facet, Debug)]
+pub struct DoctorArgs {
+    /// Also write a zip with the results, config and log to the cache for sharing.
+    #[facet(args::named, default)]
+    pub report: bool,
+}
+
+#[derive(Facet, Debug)]
+pub struct StatsArgs {
+    /// How many of the most recent active days to list (7 by default).
+    #[facet(args::named, default)]
+    pub days: Option<usize>,
+}
+
+#[derive(Facet, Debug)]
+pub struct ServeArgs {
+    /// Speak line-delimited JSON-RPC 2.0 on stdin and stdout.
+    #[facet(args::named, default)]
+    pub stdio: bool,
+    /// Serve the REST API (needs a build with the `http` feature).
+    #[facet(args::named, default)]
+    pub http: bool,
+    /// Address for `--http`; 127.0.0.1:7878 by default.
+    #[facet(args::named, default)]
+    pub bind: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+pub struct PathsArgs {
+    /// Print the paths as JSON.
+    #[facet(args::named, default)]
+    pub json: bool,
+    /// Create any missing directories.
+    #[facet(args::named, default)]
+    pub create: bool,
+}
+
+#[derive(Facet, Debug)]
+pub struct SchemaArgs {
+    #[facet(args::subcommand, default)]
+    pub command: SchemaCommand,
+}
+
+/// Which JSON Schema to print.
+#[derive(Facet, Debug, Default)]
+#[repr(u8)]
+pub enum SchemaCommand {
+    /// Schema for `config.toml`.
+    #[default]
+    Config,
+    /// Schema for the command-line arguments.
+    Cli,
 }
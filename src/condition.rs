@@ -0,0 +1,368 @@
+use eyre::{Context, Result, bail};
+use regex::Regex;
+use std::fmt;
+#[cfg(windows)]
+use std::path::Path;
+#[cfg(windows)]
+use windows::Win32::Foundation::{CloseHandle, HWND};
+#[cfg(windows)]
+use windows::Win32::System::Threading::{
+    OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    QueryFullProcessImageNameW,
+};
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetClassNameW, GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId,
+};
+#[cfg(windows)]
+use windows::core::PWSTR;
+
+/// The parts of the foreground window a binding condition can match against.
+#[cfg(any(windows, test))]
+#[derive(Debug, Clone, Default)]
+pub struct ForegroundWindow {
+    /// Executable file name, e.g. `devenv.exe`.
+    pub process: String,
+    pub class: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowField {
+    Process,
+    Class,
+    Title,
+}
+
+impl WindowField {
+    fn name(self) -> &'static str {
+        match self {
+            WindowField::Process => "process",
+            WindowField::Class => "class",
+            WindowField::Title => "title",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Case-insensitive glob supporting `*` and `?`.
+    Glob(String),
+    /// Regular expression written as `/expression/`.
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value
+            .strip_prefix('/')
+            .and_then(|value| value.strip_suffix('/'))
+        {
+            Some(expression) => Ok(Pattern::Regex(
+                Regex::new(expression)
+                    .wrap_err_with(|| format!("Invalid regular expression /{expression}/"))?,
+            )),
+            None => Ok(Pattern::Glob(value.to_string())),
+        }
+    }
+
+    #[cfg(any(windows, test))]
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_matches(
+                &glob.to_lowercase().chars().collect::<Vec<_>>(),
+                &text.to_lowercase().chars().collect::<Vec<_>>(),
+            ),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Glob(glob) => write!(f, "{glob}"),
+            Pattern::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+        }
+    }
+}
+
+/// Restricts a binding to (or excludes it from) windows whose process, class or title match.
+///
+/// Written after the action as `if <field>:<pattern>` or `unless <field>:<pattern>`.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub negate: bool,
+    pub field: WindowField,
+    pub pattern: Pattern,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (keyword, rest) = text
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| eyre::eyre!("Condition must look like 'if <field>:<pattern>'"))?;
+        let negate = match keyword.to_ascii_lowercase().as_str() {
+            "if" => false,
+            "unless" => true,
+            other => bail!("Condition must start with 'if' or 'unless', found '{other}'"),
+        };
+
+        let (field, pattern) = rest
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| eyre::eyre!("Condition must look like '<field>:<pattern>'"))?;
+        let field = match field.trim().to_ascii_lowercase().as_str() {
+            "process" | "exe" => WindowField::Process,
+            "class" => WindowField::Class,
+            "title" => WindowField::Title,
+            other => bail!("Unknown condition field '{other}', expected process, class or title"),
+        };
+
+        Ok(Self {
+            negate,
+            field,
+            pattern: Pattern::parse(pattern.trim())?,
+        })
+    }
+
+    #[cfg(any(windows, test))]
+    pub fn matches(&self, window: &ForegroundWindow) -> bool {
+        let value = match self.field {
+            WindowField::Process => &window.process,
+            WindowField::Class => &window.class,
+            WindowField::Title => &window.title,
+        };
+        self.pattern.matches(value) != self.negate
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = if self.negate { "unless" } else { "if" };
+        write!(f, "{keyword} {}:{}", self.field.name(), self.pattern)
+    }
+}
+
+#[cfg(any(windows, test))]
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some((&expected, rest)) => text.split_first().is_some_and(|(&actual, text)| {
+            (expected == '?' || expected == actual) && glob_matches(rest, text)
+        }),
+    }
+}
+
+#[cfg(windows)]
+pub fn foreground_window() -> Result<ForegroundWindow> {
+    let hwnd = unsafe { GetForegroundWindow() };
+    if hwnd.0.is_null() {
+        bail!("No foreground window")
+    }
+
+    Ok(ForegroundWindow {
+        process: window_process_name(hwnd)?,
+        class: window_class(hwnd),
+        title: window_title(hwnd),
+    })
+}
+
+#[cfg(windows)]
+fn window_class(hwnd: HWND) -> String {
+    let mut buffer = [0u16; 256];
+    let length = unsafe { GetClassNameW(hwnd, &mut buffer) };
+    String::from_utf16_lossy(&buffer[..usize::try_from(length).unwrap_or_default()])
+}
+
+#[cfg(windows)]
+fn window_title(hwnd: HWND) -> String {
+    let mut buffer = [0u16; 512];
+    let length = unsafe { GetWindowTextW(hwnd, &mut buffer) };
+    String::from_utf16_lossy(&buffer[..usize::try_from(length).unwrap_or_default()])
+}
+
+#[cfg(windows)]
+fn window_process_name(hwnd: HWND) -> Result<String> {
+    let mut process_id = 0u32;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&raw mut process_id)) };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) }
+        .wrap_err_with(|| format!("Failed to open foreground process {process_id}"))?;
+
+    let mut buffer = [0u16; 1024];
+    let mut length = buffer.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &raw mut length,
+        )
+    };
+    let _ = unsafe { CloseHandle(process) };
+    result.wrap_err("Failed to query foreground process image name")?;
+
+    let image = String::from_utf16_lossy(&buffer[..length as usize]);
+    Ok(Path::new(&image)
+        .file_name()
+        .map_or(image.clone(), |name| name.to_string_lossy().into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        Pattern::Glob(pattern.to_string()).matches(text)
+    }
+
+    fn window(process: &str, class: &str, title: &str) -> ForegroundWindow {
+        ForegroundWindow {
+            process: process.to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let cases = [
+            ("*", "", true),
+            ("*", "anything", true),
+            ("code.exe", "code.exe", true),
+            ("code.exe", "code.exe.bak", false),
+            ("*.exe", "devenv.exe", true),
+            ("*.exe", "devenv.ex", false),
+            ("dev*.exe", "devenv.exe", true),
+            ("*env*", "devenv.exe", true),
+            ("*a*b*", "xaxxbx", true),
+            ("*a*b*", "xbxxax", false),
+            ("?", "a", true),
+            ("?", "", false),
+            ("?", "ab", false),
+            ("??de.exe", "code.exe", true),
+            ("c?de*", "code.exe", true),
+            ("*?", "", false),
+            ("", "", true),
+            ("", "a", false),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob(pattern, text), expected, "{pattern:?} ~ {text:?}");
+        }
+    }
+
+    #[test]
+    fn glob_ignores_case() {
+        let cases = [
+            ("CODE.EXE", "code.exe"),
+            ("code.exe", "Code.Exe"),
+            ("*VISUAL STUDIO*", "Solution - Microsoft Visual Studio"),
+            ("ÉDITEUR*", "éditeur de texte"),
+        ];
+        for (pattern, text) in cases {
+            assert!(glob(pattern, text), "{pattern:?} ~ {text:?}");
+        }
+    }
+
+    #[test]
+    fn regex_patterns() {
+        let pattern = Pattern::parse("/^(code|devenv)\\.exe$/").unwrap();
+        assert!(pattern.matches("code.exe"));
+        assert!(pattern.matches("devenv.exe"));
+        assert!(!pattern.matches("vscode.exe"));
+        assert!(!pattern.matches("CODE.EXE"));
+        assert!(Pattern::parse("/(?i)^code/").unwrap().matches("Code.exe"));
+        assert_eq!(pattern.to_string(), "/^(code|devenv)\\.exe$/");
+    }
+
+    #[test]
+    fn parse_conditions() {
+        let cases = [
+            (
+                "if process:code.exe",
+                false,
+                WindowField::Process,
+                "code.exe",
+            ),
+            ("IF exe:code.exe", false, WindowField::Process, "code.exe"),
+            (
+                "unless class:CabinetWClass",
+                true,
+                WindowField::Class,
+                "CabinetWClass",
+            ),
+            (
+                "Unless Title: *Remote Desktop* ",
+                true,
+                WindowField::Title,
+                "*Remote Desktop*",
+            ),
+            ("  if   title:/^Zoom/", false, WindowField::Title, "/^Zoom/"),
+            ("if title:a:b", false, WindowField::Title, "a:b"),
+        ];
+        for (text, negate, field, pattern) in cases {
+            let condition = Condition::parse(text).unwrap();
+            assert_eq!(condition.negate, negate, "{text:?}");
+            assert_eq!(condition.field, field, "{text:?}");
+            assert_eq!(condition.pattern.to_string(), pattern, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_conditions() {
+        let cases = [
+            "",
+            "if",
+            "process:code.exe",
+            "when process:code.exe",
+            "if code.exe",
+            "if pid:42",
+            "unless title:/(unclosed/",
+        ];
+        for text in cases {
+            assert!(Condition::parse(text).is_err(), "{text:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for text in [
+            "if process:code.exe",
+            "unless title:/^Zoom/",
+            "if class:*Cabinet*",
+        ] {
+            assert_eq!(Condition::parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn conditions_match_the_window() {
+        let editor = window(
+            "Code.exe",
+            "Chrome_WidgetWin_1",
+            "main.rs - tb - Visual Studio Code",
+        );
+        let explorer = window("explorer.exe", "CabinetWClass", "Downloads");
+        let cases = [
+            ("if process:code.exe", true, false),
+            ("unless process:code.exe", false, true),
+            ("if class:cabinet*", false, true),
+            ("unless class:cabinet*", true, false),
+            ("if title:*visual studio code", true, false),
+            ("if title:/Downloads$/", false, true),
+            ("unless title:/Downloads$/", true, false),
+        ];
+        for (text, on_editor, on_explorer) in cases {
+            let condition = Condition::parse(text).unwrap();
+            assert_eq!(condition.matches(&editor), on_editor, "{text:?} on editor");
+            assert_eq!(
+                condition.matches(&explorer),
+                on_explorer,
+                "{text:?} on explorer"
+            );
+        }
+    }
+}
@@ -0,0 +1,665 @@
+use crate::action::Action;
+use crate::condition::Condition;
+use crate::hotkey::DEFAULT_HOTKEY_EXPRESSION;
+use crate::keymap::{self, Binding, Trigger};
+use crate::paths::{self, CONFIG_FILE, SNAPSHOT_DIR};
+use crate::settings::{self, Value};
+use crate::store::{self, FileLock};
+use eyre::{Context, Result, bail, eyre};
+use facet::Facet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_VERSION: u32 = 1;
+const CONFIG_LOCK_FILE: &str = "config.toml.lock";
+const LEGACY_HOTKEY_FILE: &str = "hotkey.txt";
+const LEGACY_HOTKEY_BACKUP_FILE: &str = "hotkey.txt.migrated";
+
+pub const LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+pub const LOG_FORMATS: [&str; 3] = ["text", "json", "compact"];
+
+/// Everything tb persists, stored as TOML in `config.toml` under the app home.
+#[derive(Facet, Debug, Clone)]
+#[facet(default)]
+pub struct Config {
+    /// Schema version of this file; bumped when a migration is needed.
+    pub version: u32,
+    pub bindings: Vec<BindingConfig>,
+    pub startup: StartupConfig,
+    pub logging: LoggingConfig,
+    pub policies: PolicyConfig,
+    pub cache: CacheConfig,
+    pub hooks: HooksConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            bindings: vec![BindingConfig {
+                trigger: DEFAULT_HOTKEY_EXPRESSION.to_string(),
+                action: "toggle".to_string(),
+                condition: None,
+            }],
+            startup: StartupConfig::default(),
+            logging: LoggingConfig::default(),
+            policies: PolicyConfig::default(),
+            cache: CacheConfig::default(),
+            hooks: HooksConfig::default(),
+        }
+    }
+}
+
+/// One `[[bindings]]` entry; see [`Binding`] for the accepted trigger and condition syntax.
+#[derive(Facet, Debug, Clone, PartialEq, Eq)]
+pub struct BindingConfig {
+    pub trigger: String,
+    pub action: String,
+    #[facet(default)]
+    pub condition: Option<String>,
+}
+
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct StartupConfig {
+    /// Auto-hide state applied when the tray starts; left unchanged when unset.
+    pub auto_hide: Option<bool>,
+    /// Open the log console as soon as the tray starts.
+    pub show_logs: bool,
+}
+
+#[derive(Facet, Debug, Clone)]
+#[facet(default)]
+pub struct LoggingConfig {
+    /// One of `error`, `warn`, `info`, `debug` or `trace`. `--debug` overrides it.
+    pub level: String,
+    /// `text`, `json` (one object per event, for log collectors) or `compact`.
+    pub format: String,
+    /// The log file is rotated once it grows past this size; 0 rotates daily only.
+    pub max_file_size_mb: u32,
+    /// How many rotated log files to keep besides the active one.
+    pub max_files: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: "text".to_string(),
+            max_file_size_mb: 5,
+            max_files: 10,
+        }
+    }
+}
+
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct PolicyConfig {
+    /// Put auto-hide back to how it was when the tray started once the tray exits.
+    pub restore_on_exit: bool,
+    /// Re-apply the last state tb chose whenever something else changes it.
+    pub enforce: bool,
+}
+
+#[derive(Facet, Debug, Clone)]
+#[facet(default)]
+pub struct CacheConfig {
+    /// Cache files older than this many days are removed when the tray starts; 0 keeps them.
+    pub retention_days: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+/// Commands run through `cmd /C` (`sh -c` off Windows) when the taskbar changes; see
+/// [`crate::hooks`].
+#[derive(Facet, Debug, Clone)]
+#[facet(default)]
+pub struct HooksConfig {
+    /// Run after auto-hide is turned on.
+    pub on_hide: Option<String>,
+    /// Run after auto-hide is turned off.
+    pub on_show: Option<String>,
+    /// Run when an action fails to change the taskbar.
+    pub on_toggle_failed: Option<String>,
+    /// Hooks still running after this many seconds are killed, with anything they started; 0 lets
+    /// them run.
+    pub timeout_seconds: u32,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_hide: None,
+            on_show: None,
+            on_toggle_failed: None,
+            timeout_seconds: 30,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(source: &str, path: &Path) -> Result<Self> {
+        let config: Config = facet_toml::from_str(source)
+            .map_err(|error| eyre!("{}: {error}", path.display()))?;
+        config.validate(source, path)?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        facet_toml::to_string(self).map_err(|error| eyre!("Failed to serialize config: {error}"))
+    }
+
+    /// Checks the values the TOML schema cannot, reporting the offending line and column.
+    pub fn validate(&self, source: &str, path: &Path) -> Result<()> {
+        if self.version > CONFIG_VERSION {
+            bail!(
+                "{}: config version {} was written by a newer tb (this build supports {CONFIG_VERSION})",
+                locate(source, path, None, "version"),
+                self.version
+            )
+        }
+
+        for (index, binding) in self.bindings.iter().enumerate() {
+            let table = Some(("bindings", index));
+            Trigger::parse(&binding.trigger).map_err(|error| {
+                eyre!(
+                    "{}: bindings[{index}].trigger: {error}",
+                    locate(source, path, table, "trigger")
+                )
+            })?;
+            Action::parse(&binding.action).map_err(|error| {
+                eyre!(
+                    "{}: bindings[{index}].action: {error}",
+                    locate(source, path, table, "action")
+                )
+            })?;
+            if let Some(condition) = &binding.condition {
+                Condition::parse(condition).map_err(|error| {
+                    eyre!(
+                        "{}: bindings[{index}].condition: {error}",
+                        locate(source, path, table, "condition")
+                    )
+                })?;
+            }
+        }
+
+        if !LOG_LEVELS.contains(&self.logging.level.as_str()) {
+            bail!(
+                "{}: logging.level must be one of {}, found '{}'",
+                locate(source, path, Some(("logging", 0)), "level"),
+                LOG_LEVELS.join(", "),
+                self.logging.level
+            )
+        }
+        if !LOG_FORMATS.contains(&self.logging.format.as_str()) {
+            bail!(
+                "{}: logging.format must be one of {}, found '{}'",
+                locate(source, path, Some(("logging", 0)), "format"),
+                LOG_FORMATS.join(", "),
+                self.logging.format
+            )
+        }
+
+        Ok(())
+    }
+
+    pub fn keymap(&self) -> Result<Vec<Binding>> {
+        if self.bindings.is_empty() {
+            return keymap::default_keymap();
+        }
+        self.bindings
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| {
+                Binding::from_config(binding).wrap_err_with(|| format!("Invalid bindings[{index}]"))
+            })
+            .collect()
+    }
+}
+
+/// Formats `path:line:column` for `key` inside `[table]` (or the `index`th `[[table]]`).
+///
+/// Falls back to the bare path when the key is absent, e.g. because a default is in effect.
+fn locate(source: &str, path: &Path, table: Option<(&str, usize)>, key: &str) -> String {
+    match find_key(source, table, key) {
+        Some(line_index) => {
+            let line = source.lines().nth(line_index).unwrap_or_default();
+            let column = line.find('=').map_or(1, |position| {
+                let value = &line[position + 1..];
+                position + 2 + value.len() - value.trim_start().len()
+            });
+            format!("{}:{}:{column}", path.display(), line_index + 1)
+        }
+        None => path.display().to_string(),
+    }
+}
+
+pub fn has_key(source: &str, table: Option<(&str, usize)>, key: &str) -> bool {
+    find_key(source, table, key).is_some()
+}
+
+/// Returns the table each line of `source` belongs to. Header lines belong to the table they open.
+fn line_tables(source: &str) -> Vec<Option<(&str, usize)>> {
+    let mut current = None;
+    let mut array_counts: Vec<(&str, usize)> = Vec::new();
+
+    source
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix("[[").and_then(|rest| rest.strip_suffix("]]")) {
+                let name = name.trim();
+                let seen = array_counts.iter_mut().find(|(seen, _)| *seen == name);
+                let index = if let Some((_, count)) = seen {
+                    *count += 1;
+                    *count - 1
+                } else {
+                    array_counts.push((name, 1));
+                    0
+                };
+                current = Some((name, index));
+            } else if let Some(name) = trimmed.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                current = Some((name.trim(), 0));
+            }
+            current
+        })
+        .collect()
+}
+
+fn is_header(line: &str) -> bool {
+    line.trim_start().starts_with('[')
+}
+
+fn find_key(source: &str, table: Option<(&str, usize)>, key: &str) -> Option<usize> {
+    source
+        .lines()
+        .zip(line_tables(source))
+        .position(|(line, current)| {
+            current == table
+                && !is_header(line)
+                && line
+                    .split_once('=')
+                    .is_some_and(|(candidate, _)| candidate.trim() == key)
+        })
+}
+
+/// Sets (`Some`) or removes (`None`) `key = value` in `source`, leaving every other line as is.
+pub fn edit_toml(source: &str, table: Option<(&str, usize)>, key: &str, literal: Option<&str>) -> String {
+    let mut lines = source.lines().map(ToString::to_string).collect::<Vec<_>>();
+    let tables = line_tables(source);
+
+    if let Some(index) = find_key(source, table, key) {
+        match literal {
+            Some(literal) => lines[index] = format!("{key} = {literal}"),
+            None => {
+                lines.remove(index);
+            }
+        }
+    } else if let Some(literal) = literal {
+        let entry = format!("{key} = {literal}");
+        let last_in_table = tables
+            .iter()
+            .zip(&lines)
+            .enumerate()
+            .filter(|(_, (current, line))| **current == table && !line.trim().is_empty())
+            .map(|(index, _)| index)
+            .next_back();
+        match (last_in_table, table) {
+            (Some(index), _) => lines.insert(index + 1, entry),
+            (None, None) => lines.insert(0, entry),
+            (None, Some((name, _))) => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{name}]"));
+                lines.push(entry);
+            }
+        }
+    }
+
+    let mut edited = lines.join("\n");
+    edited.push('\n');
+    edited
+}
+
+/// Appends `binding` to `source` as a new `[[bindings]]` table.
+pub fn append_binding(source: &str, binding: &BindingConfig) -> String {
+    let mut appended = source.trim_end().to_string();
+    appended.push_str("\n\n[[bindings]]\n");
+    let fields = [
+        ("trigger", Some(&binding.trigger)),
+        ("action", Some(&binding.action)),
+        ("condition", binding.condition.as_ref()),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            let _ = writeln!(appended, "{key} = {}", Value::Text(value.clone()).to_toml());
+        }
+    }
+    appended
+}
+
+/// Where the user's `config.toml` lives. Nothing is created; writers make the directory.
+pub fn config_path() -> Result<PathBuf> {
+    Ok(paths::app_home()?.path().join(CONFIG_FILE))
+}
+
+/// Reads `config.toml` as written, without machine, environment or command-line layers.
+///
+/// Also returns the raw file contents, or `None` when the file does not exist yet.
+pub fn read_config_file() -> Result<(Config, Option<String>)> {
+    let path = config_path()?;
+    Ok(match read_file(&path)? {
+        Some((config, raw)) => (config, Some(raw)),
+        None => (Config::default(), None),
+    })
+}
+
+/// Reads the machine-wide `config.toml`, if there is one.
+pub fn read_machine_config_file() -> Result<Option<(Config, String)>> {
+    match machine_config_path() {
+        Ok(path) => read_file(&path),
+        Err(error) => {
+            tracing::debug!("Skipping machine-wide config: {error}");
+            Ok(None)
+        }
+    }
+}
+
+pub fn machine_config_path() -> Result<PathBuf> {
+    Ok(paths::machine_home()?.path().join(CONFIG_FILE))
+}
+
+fn read_file(path: &Path) -> Result<Option<(Config, String)>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let raw = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read configuration at {}", path.display()))?;
+    let config = Config::parse(&raw, path)?;
+    Ok(Some((config, raw)))
+}
+
+/// The config file alone; use this before modifying and saving the configuration.
+pub fn load_config_file() -> Result<Config> {
+    Ok(read_config_file()?.0)
+}
+
+/// The effective configuration with every layer applied; see [`settings::resolve`].
+pub fn load_config() -> Result<Config> {
+    Ok(settings::resolve()?.config)
+}
+
+/// Takes the config lock. Hold it from reading the config file until the modified version is
+/// saved so concurrent tb processes cannot lose each other's changes.
+pub fn lock_config() -> Result<FileLock> {
+    paths::app_home()?.ensure_dir()?;
+    FileLock::acquire(&config_lock_path()?)
+}
+
+fn config_lock_path() -> Result<PathBuf> {
+    Ok(paths::app_home()?.path().join(CONFIG_LOCK_FILE))
+}
+
+/// Where previous versions of `config.toml` are kept, newest last.
+pub fn snapshot_dir() -> Result<PathBuf> {
+    Ok(paths::app_home()?.path().join(SNAPSHOT_DIR))
+}
+
+pub fn save_config(config: &Config, lock: &FileLock) -> Result<()> {
+    write_config(&config.to_toml()?, lock)
+}
+
+/// Converts a `hotkey.txt` left by an earlier version into `config.toml`.
+///
+/// Runs only when no `config.toml` exists yet. The old file is kept as `hotkey.txt.migrated`.
+/// Returns the path of the migrated file.
+pub fn migrate_legacy_hotkeys() -> Result<Option<PathBuf>> {
+    let home = paths::app_home()?;
+    let legacy = home.path().join(LEGACY_HOTKEY_FILE);
+    let path = home.path().join(CONFIG_FILE);
+    if path.exists() || !legacy.exists() {
+        return Ok(None);
+    }
+    let lock = lock_config()?;
+    // Another tb process may have migrated while we waited for the lock.
+    if path.exists() || !legacy.exists() {
+        return Ok(None);
+    }
+
+    let raw = std::fs::read_to_string(&legacy)
+        .wrap_err_with(|| format!("Failed to read {}", legacy.display()))?;
+    let mut bindings = Vec::new();
+    for (index, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let binding = Binding::parse_line(line.trim()).wrap_err_with(|| {
+            format!("{}:{}: invalid hotkey binding", legacy.display(), index + 1)
+        })?;
+        bindings.push(binding.to_config());
+    }
+
+    let mut config = Config::default();
+    if !bindings.is_empty() {
+        config.bindings = bindings;
+    }
+    save_config(&config, &lock)?;
+    std::fs::rename(&legacy, home.path().join(LEGACY_HOTKEY_BACKUP_FILE))
+        .wrap_err_with(|| format!("Failed to retire {}", legacy.display()))?;
+    Ok(Some(legacy))
+}
+
+/// Validates and writes one setting into `config.toml`, preserving the rest of the file.
+pub fn set_config_value(key: &str, raw_value: &str) -> Result<Value> {
+    let lock = lock_config()?;
+    let (mut config, raw) = read_config_file()?;
+    let value = settings::set(&mut config, key, raw_value)?;
+    let (table, field) = settings::location(key)?;
+    let source = raw.unwrap_or_else(|| format!("version = {CONFIG_VERSION}\n"));
+
+    // Default bindings are not in the file yet, so there is no table to edit in place.
+    if table.is_some_and(|(name, _)| name == "bindings") && !source.contains("[[bindings]]") {
+        save_config(&config, &lock)?;
+        return Ok(value);
+    }
+
+    write_config(&edit_toml(&source, table, field, Some(&value.to_toml())), &lock)?;
+    Ok(value)
+}
+
+/// Removes one setting from `config.toml` so its default applies again.
+pub fn unset_config_value(key: &str) -> Result<()> {
+    let lock = lock_config()?;
+    let (mut config, raw) = read_config_file()?;
+    settings::unset(&mut config, key)?;
+    let Some(source) = raw else {
+        return Ok(());
+    };
+    let (table, field) = settings::location(key)?;
+    write_config(&edit_toml(&source, table, field, None), &lock)
+}
+
+/// Validates `contents`, snapshots the current file and atomically replaces it.
+///
+/// Taking the lock is left to the caller, which must hold it across its read as well.
+pub fn write_config(contents: &str, lock: &FileLock) -> Result<()> {
+    lock.ensure_guards(&config_lock_path()?)?;
+    let path = config_path()?;
+    Config::parse(contents, &path)?;
+    store::snapshot(&path, &snapshot_dir()?)?;
+    store::write_atomic(&path, contents.as_bytes())
+        .wrap_err_with(|| format!("Failed to write configuration at {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "# tb settings\n\
+                          version = 1\n\
+                          \n\
+                          [logging]\n\
+                          # chatty while debugging\n\
+                          level = \"debug\"\n\
+                          \n\
+                          [[bindings]]\n\
+                          trigger = \"Ctrl+Shift+B\"\n\
+                          action = \"toggle\"\n\
+                          \n\
+                          [[bindings]]\n\
+                          # the second one\n\
+                          trigger = \"Ctrl+Alt+H\"\n\
+                          action = \"enable\"\n";
+
+    #[test]
+    fn edit_toml_keeps_everything_else() {
+        let cases = [
+            (
+                Some(("logging", 0)),
+                "level",
+                Some("\"warn\""),
+                SOURCE.replace("level = \"debug\"", "level = \"warn\""),
+            ),
+            (
+                Some(("logging", 0)),
+                "max_files",
+                Some("3"),
+                SOURCE.replace("level = \"debug\"\n", "level = \"debug\"\nmax_files = 3\n"),
+            ),
+            (
+                Some(("logging", 0)),
+                "level",
+                None,
+                SOURCE.replace("level = \"debug\"\n", ""),
+            ),
+            (
+                Some(("bindings", 1)),
+                "action",
+                Some("\"disable\""),
+                SOURCE.replace("action = \"enable\"", "action = \"disable\""),
+            ),
+            (
+                Some(("policies", 0)),
+                "enforce",
+                Some("true"),
+                format!("{SOURCE}\n[policies]\nenforce = true\n"),
+            ),
+            (None, "version", None, SOURCE.replace("version = 1\n", "")),
+        ];
+        for (table, key, literal, expected) in cases {
+            assert_eq!(
+                edit_toml(SOURCE, table, key, literal),
+                expected,
+                "{table:?} {key} {literal:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn locate_points_at_the_value() {
+        let path = Path::new("config.toml");
+        let cases = [
+            (None, "version", "config.toml:2:11"),
+            (Some(("logging", 0)), "level", "config.toml:6:9"),
+            (Some(("bindings", 1)), "trigger", "config.toml:14:11"),
+            (Some(("bindings", 1)), "condition", "config.toml"),
+            (Some(("cache", 0)), "retention_days", "config.toml"),
+        ];
+        for (table, key, expected) in cases {
+            assert_eq!(
+                locate(SOURCE, path, table, key),
+                expected,
+                "{table:?} {key}"
+            );
+        }
+    }
+
+    #[test]
+    fn validate_reports_file_line_and_column() {
+        let path = Path::new("config.toml");
+        let cases = [
+            (
+                SOURCE.replace("Ctrl+Alt+H", "Ctrl+Alt"),
+                "config.toml:14:11: bindings[1].trigger",
+            ),
+            (
+                SOURCE.replace("\"enable\"", "\"explode\""),
+                "config.toml:15:10: bindings[1].action",
+            ),
+            (
+                SOURCE.replace("\"debug\"", "\"loud\""),
+                "config.toml:6:9: logging.level must be one of",
+            ),
+            (
+                SOURCE.replace("version = 1", "version = 99"),
+                "config.toml:2:11: config version 99",
+            ),
+        ];
+        for (source, expected) in cases {
+            let error = Config::parse(&source, path).unwrap_err().to_string();
+            assert!(error.starts_with(expected), "{error}");
+        }
+        Config::parse(SOURCE, path).unwrap();
+    }
+
+    #[test]
+    fn migrate_legacy_hotkeys_writes_the_config_once() {
+        let _dirs = paths::scratch_dirs();
+        let home = paths::app_home().unwrap();
+        home.ensure_dir().unwrap();
+        let legacy = home.path().join(LEGACY_HOTKEY_FILE);
+        std::fs::write(
+            &legacy,
+            "Ctrl+Alt+T\n\nCtrl+Shift+H = disable unless process:devenv.exe\n",
+        )
+        .unwrap();
+
+        assert_eq!(migrate_legacy_hotkeys().unwrap(), Some(legacy.clone()));
+        let config = load_config_file().unwrap();
+        assert_eq!(
+            config.bindings,
+            [
+                BindingConfig {
+                    trigger: "Ctrl+Alt+T".to_string(),
+                    action: "toggle".to_string(),
+                    condition: None,
+                },
+                BindingConfig {
+                    trigger: "Ctrl+Shift+H".to_string(),
+                    action: "disable".to_string(),
+                    condition: Some("unless process:devenv.exe".to_string()),
+                },
+            ]
+        );
+        assert!(!legacy.exists());
+        assert!(home.path().join(LEGACY_HOTKEY_BACKUP_FILE).exists());
+
+        // An existing config.toml wins over a hotkey.txt that reappears.
+        std::fs::write(&legacy, "Ctrl+Alt+Q\n").unwrap();
+        assert_eq!(migrate_legacy_hotkeys().unwrap(), None);
+        assert_eq!(load_config_file().unwrap().bindings, config.bindings);
+    }
+
+    #[test]
+    fn migrate_legacy_hotkeys_reports_the_bad_line() {
+        let _dirs = paths::scratch_dirs();
+        let home = paths::app_home().unwrap();
+        home.ensure_dir().unwrap();
+        let legacy = home.path().join(LEGACY_HOTKEY_FILE);
+        std::fs::write(&legacy, "Ctrl+Alt+T\nCtrl+Alt+T = explode\n").unwrap();
+
+        let error = migrate_legacy_hotkeys().unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("{}:2: invalid hotkey binding", legacy.display())
+        );
+        assert!(legacy.exists());
+        assert!(!config_path().unwrap().exists());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::action::{Action, Source};
+use crate::events::Event;
+use crate::history;
+use crate::ipc;
+use crate::taskbar;
+use eyre::{Context, Result};
+use std::time::Duration;
+
+/// How often [`watch`] polls the taskbar.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs `action` through the running tray when there is one, so its history and enforcement state
+/// stay current, and against the taskbar directly otherwise. Returns the resulting state.
+pub fn perform(action: Action, source: Source) -> Result<bool> {
+    match ipc::send(&ipc::Request::new(action.name()).with_source(source))? {
+        Some(response) => response.into_auto_hide(),
+        None => action.perform(source),
+    }
+}
+
+/// Reverts the most recent change, through the running tray when there is one. Returns the
+/// reverted change and the resulting state, or `None` when there is nothing to undo.
+pub fn undo() -> Result<Option<(Event, bool)>> {
+    match ipc::send(&ipc::Request::new("undo"))? {
+        Some(mut response) => match response.undone.take() {
+            Some(change) => Ok(Some((change, response.into_auto_hide()?))),
+            None if response.ok => Ok(None),
+            None => response.into_auto_hide().map(|_| None),
+        },
+        None => history::undo(),
+    }
+}
+
+/// The current auto-hide state, as the running tray sees it when there is one.
+pub fn state() -> Result<bool> {
+    match ipc::send(&ipc::Request::new("status"))? {
+        Some(response) => response.into_auto_hide(),
+        None => taskbar::is_taskbar_auto_hide_enabled(),
+    }
+}
+
+/// Calls `on_change` with the new state from a background thread whenever auto-hide changes,
+/// whoever changed it. Stops once `on_change` returns `false`.
+pub fn watch(mut on_change: impl FnMut(bool) -> bool + Send + 'static) -> Result<()> {
+    let mut last = taskbar::is_taskbar_auto_hide_enabled().ok();
+    std::thread::Builder::new()
+        .name("tb-watch".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let current = match taskbar::is_taskbar_auto_hide_enabled() {
+                    Ok(current) => current,
+                    Err(error) => {
+                        tracing::debug!("Failed to poll the taskbar: {error:#}");
+                        continue;
+                    }
+                };
+                if last == Some(current) {
+                    continue;
+                }
+                last = Some(current);
+                if !on_change(current) {
+                    return;
+                }
+            }
+        })
+        .wrap_err("Failed to start the taskbar watcher thread")?;
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use crate::config;
+use crate::keymap;
+use crate::logfile;
+use crate::paths;
+use crate::settings;
+use crate::taskbar;
+use crate::tray;
+use eyre::{Context, Result, bail};
+use std::ffi::c_void;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Security::{
+    GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation, TOKEN_ELEVATION,
+    TOKEN_MANDATORY_LABEL, TOKEN_QUERY, TokenElevation, TokenIntegrityLevel,
+};
+use windows::Win32::Storage::FileSystem::{
+    GetFileVersionInfoSizeW, GetFileVersionInfoW, VS_FIXEDFILEINFO, VerQueryValueW,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    HOT_KEY_MODIFIERS, RegisterHotKey, UnregisterHotKey,
+};
+use windows::core::{HSTRING, w};
+use zip::write::SimpleFileOptions;
+
+/// Reports are written here, under the cache directory.
+const REPORT_DIR: &str = "reports";
+/// Id used while probing hotkeys, well clear of the tray's own.
+const PROBE_HOTKEY_ID: i32 = 0x7000;
+
+// Mandatory integrity level RIDs, see `winnt.h`.
+const LOW_RID: u32 = 0x1000;
+const MEDIUM_RID: u32 = 0x2000;
+const HIGH_RID: u32 = 0x3000;
+const SYSTEM_RID: u32 = 0x4000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.name, self.detail)
+    }
+}
+
+/// Runs every diagnostic. A failing check is reported as [`Status::Fail`] rather than aborting.
+pub fn run_checks() -> Vec<Check> {
+    let mut checks = vec![check_taskbar_window(), check_appbar_state(), check_config()];
+    checks.extend(check_hotkeys());
+    checks.extend(check_directories());
+    checks.push(check_tray_instances());
+    checks.push(check_elevation());
+    checks.push(check_explorer_version());
+    checks
+}
+
+fn check_taskbar_window() -> Check {
+    const NAME: &str = "taskbar window";
+    match taskbar::find_taskbar_window() {
+        Ok(hwnd) => Check::new(NAME, Status::Pass, format!("Shell_TrayWnd is {:?}", hwnd.0)),
+        Err(error) => Check::new(NAME, Status::Fail, format!("{error:#}")),
+    }
+}
+
+fn check_appbar_state() -> Check {
+    const NAME: &str = "appbar state";
+    match taskbar::is_taskbar_auto_hide_enabled() {
+        Ok(enabled) => Check::new(
+            NAME,
+            Status::Pass,
+            format!("auto-hide {}", if enabled { "enabled" } else { "disabled" }),
+        ),
+        Err(error) => Check::new(NAME, Status::Fail, format!("{error:#}")),
+    }
+}
+
+fn check_config() -> Check {
+    const NAME: &str = "config";
+    let path = config::config_path()
+        .map_or_else(|error| format!("{error:#}"), |path| path.display().to_string());
+    match config::read_config_file().and_then(|_| settings::resolve()) {
+        Ok(_) => Check::new(NAME, Status::Pass, format!("{path} is valid")),
+        Err(error) => Check::new(NAME, Status::Fail, format!("{error:#}")),
+    }
+}
+
+/// Registers and immediately releases each bound hotkey to see whether another program owns it.
+///
+/// A running tray holds its own hotkeys, so they are only reported as taken when no tray is up.
+fn check_hotkeys() -> Vec<Check> {
+    const NAME: &str = "hotkey";
+    let bindings = match keymap::load_keymap() {
+        Ok(bindings) => bindings,
+        Err(error) => return vec![Check::new(NAME, Status::Fail, format!("{error:#}"))],
+    };
+    let tray_running = tray::running_tray_count() > 0;
+    let mut seen = Vec::new();
+    let mut checks = Vec::new();
+    for binding in &bindings {
+        let Some(hotkey) = binding.trigger.hotkey() else {
+            continue;
+        };
+        if seen.contains(&hotkey.expression) {
+            continue;
+        }
+        seen.push(hotkey.expression.clone());
+
+        let registration = hotkey.registration;
+        let available = unsafe {
+            RegisterHotKey(
+                None,
+                PROBE_HOTKEY_ID,
+                HOT_KEY_MODIFIERS(registration.modifiers),
+                registration.vk,
+            )
+        }
+        .is_ok();
+        if available {
+            let _ = unsafe { UnregisterHotKey(None, PROBE_HOTKEY_ID) };
+        }
+        let check = match (available, tray_running) {
+            (true, _) => Check::new(
+                NAME,
+                Status::Pass,
+                format!("{} is available", hotkey.expression),
+            ),
+            (false, true) => Check::new(
+                NAME,
+                Status::Pass,
+                format!("{} is held, presumably by the running tray", hotkey.expression),
+            ),
+            (false, false) => Check::new(
+                NAME,
+                Status::Warn,
+                format!("{} is registered by another program", hotkey.expression),
+            ),
+        };
+        checks.push(check);
+    }
+    if checks.is_empty() {
+        checks.push(Check::new(NAME, Status::Pass, "no hotkeys are bound"));
+    }
+    checks
+}
+
+fn check_directories() -> Vec<Check> {
+    const NAME: &str = "writable";
+    let directories = [
+        ("config", paths::app_home().map(|home| home.path().to_path_buf())),
+        ("cache", paths::cache_home().map(|cache| cache.path().to_path_buf())),
+        ("logs", paths::log_dir()),
+    ];
+    directories
+        .into_iter()
+        .map(|(label, dir)| {
+            match dir.and_then(|dir| probe_writable(&dir).map(|()| dir)) {
+                Ok(dir) => Check::new(NAME, Status::Pass, format!("{label} {}", dir.display())),
+                Err(error) => Check::new(NAME, Status::Fail, format!("{label}: {error:#}")),
+            }
+        })
+        .collect()
+}
+
+fn probe_writable(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    let probe = dir.join(format!(".tb-doctor-{}.tmp", std::process::id()));
+    std::fs::write(&probe, b"tb")
+        .wrap_err_with(|| format!("Failed to write to {}", dir.display()))?;
+    std::fs::remove_file(&probe)
+        .wrap_err_with(|| format!("Failed to remove {}", probe.display()))
+}
+
+fn check_tray_instances() -> Check {
+    const NAME: &str = "tray";
+    match tray::running_tray_count() {
+        0 => Check::new(NAME, Status::Warn, "no tray is running"),
+        1 => Check::new(NAME, Status::Pass, "one tray is running"),
+        count => Check::new(
+            NAME,
+            Status::Warn,
+            format!("{count} trays are running; hotkeys and mouse triggers will conflict"),
+        ),
+    }
+}
+
+fn check_elevation() -> Check {
+    const NAME: &str = "elevation";
+    match token_info() {
+        // An elevated tray cannot be reached from unelevated `tb` commands, and vice versa.
+        Ok((true, integrity)) => Check::new(
+            NAME,
+            Status::Warn,
+            format!("elevated, {integrity} integrity; an unelevated tray cannot talk to this one"),
+        ),
+        Ok((false, integrity)) => {
+            Check::new(NAME, Status::Pass, format!("not elevated, {integrity} integrity"))
+        }
+        Err(error) => Check::new(NAME, Status::Warn, format!("{error:#}")),
+    }
+}
+
+/// Whether the process token is elevated, and its integrity level.
+fn token_info() -> Result<(bool, &'static str)> {
+    let mut token = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &raw mut token) }
+        .wrap_err("Failed to open the process token")?;
+
+    let mut elevation = TOKEN_ELEVATION::default();
+    let mut length = 0u32;
+    let elevated = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            Some((&raw mut elevation).cast()),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &raw mut length,
+        )
+    }
+    .map(|()| elevation.TokenIsElevated != 0);
+
+    // `u64` keeps the buffer aligned for the `TOKEN_MANDATORY_LABEL` written into it.
+    let mut buffer = [0u64; 32];
+    let integrity = unsafe {
+        GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            Some(buffer.as_mut_ptr().cast()),
+            size_of_val(&buffer) as u32,
+            &raw mut length,
+        )
+    }
+    .map(|()| {
+        let label = unsafe { &*buffer.as_ptr().cast::<TOKEN_MANDATORY_LABEL>() };
+        let sid = label.Label.Sid;
+        let count = unsafe { *GetSidSubAuthorityCount(sid) };
+        let rid = unsafe { *GetSidSubAuthority(sid, u32::from(count.saturating_sub(1))) };
+        integrity_name(rid)
+    });
+    let _ = unsafe { CloseHandle(token) };
+
+    Ok((
+        elevated.wrap_err("Failed to query token elevation")?,
+        integrity.unwrap_or("unknown"),
+    ))
+}
+
+fn integrity_name(rid: u32) -> &'static str {
+    match rid {
+        SYSTEM_RID.. => "system",
+        HIGH_RID.. => "high",
+        MEDIUM_RID.. => "medium",
+        LOW_RID.. => "low",
+        _ => "untrusted",
+    }
+}
+
+fn check_explorer_version() -> Check {
+    const NAME: &str = "explorer";
+    match explorer_version() {
+        Ok(version) => Check::new(NAME, Status::Pass, format!("explorer.exe {version}")),
+        Err(error) => Check::new(NAME, Status::Warn, format!("{error:#}")),
+    }
+}
+
+fn explorer_version() -> Result<String> {
+    let windows = std::env::var("WINDIR").unwrap_or_else(|_| r"C:\Windows".to_string());
+    let path = Path::new(&windows).join("explorer.exe");
+    let name = HSTRING::from(path.as_os_str());
+
+    let size = unsafe { GetFileVersionInfoSizeW(&name, None) };
+    if size == 0 {
+        bail!("{} has no version information", path.display())
+    }
+    let mut data = vec![0u8; size as usize];
+    unsafe { GetFileVersionInfoW(&name, None, size, data.as_mut_ptr().cast()) }
+        .wrap_err_with(|| format!("Failed to read the version of {}", path.display()))?;
+
+    let mut info: *mut c_void = std::ptr::null_mut();
+    let mut length = 0u32;
+    let found =
+        unsafe { VerQueryValueW(data.as_ptr().cast(), w!("\\"), &raw mut info, &raw mut length) };
+    if !found.as_bool() || info.is_null() {
+        bail!("{} has no fixed version information", path.display())
+    }
+    let info = unsafe { &*info.cast::<VS_FIXEDFILEINFO>() };
+    Ok(format!(
+        "{}.{}.{}.{}",
+        info.dwFileVersionMS >> 16,
+        info.dwFileVersionMS & 0xffff,
+        info.dwFileVersionLS >> 16,
+        info.dwFileVersionLS & 0xffff
+    ))
+}
+
+/// Writes `doctor-<unix millis>.zip` under the cache with the check results, the config files
+/// and the active log, for attaching to bug reports.
+pub fn write_report(checks: &[Check], version: &str) -> Result<PathBuf> {
+    let dir = paths::cache_home()?.path().join(REPORT_DIR);
+    std::fs::create_dir_all(&dir)
+        .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("doctor-{millis}.zip"));
+
+    let mut summary = format!("tb {version}\n\n");
+    for check in checks {
+        let _ = writeln!(summary, "{check}");
+    }
+
+    let file = std::fs::File::create(&path)
+        .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = SimpleFileOptions::default();
+    zip.start_file("report.txt", options)?;
+    zip.write_all(summary.as_bytes())?;
+    let attachments = [
+        ("config.toml", config::config_path()),
+        ("machine-config.toml", config::machine_config_path()),
+        (logfile::LOG_FILE, logfile::log_path()),
+    ];
+    for (name, source) in attachments {
+        let Ok(source) = source else {
+            continue;
+        };
+        let Ok(contents) = std::fs::read(&source) else {
+            continue;
+        };
+        zip.start_file(name, options)?;
+        zip.write_all(&contents)?;
+    }
+    zip.finish()
+        .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,272 @@
+use crate::action::{Action, Source};
+use crate::paths;
+use eyre::{Context, Result};
+use facet::Facet;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// One performed action, as a line of [`paths::EVENTS_FILE`].
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct Event {
+    /// Unix milliseconds.
+    pub time: u64,
+    pub action: String,
+    pub source: String,
+    /// Auto-hide state before and after the action.
+    pub previous: bool,
+    pub new: bool,
+}
+
+impl Event {
+    /// An action performed just now.
+    pub fn new(action: Action, source: Source, previous: bool, new: bool) -> Self {
+        Self {
+            time: now_millis(),
+            action: action.name().to_string(),
+            source: source.name().to_string(),
+            previous,
+            new,
+        }
+    }
+
+    pub fn is_transition(&self) -> bool {
+        self.previous != self.new
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} UTC  {:<7}  {}: {} -> {}",
+            format_time(self.time),
+            self.source,
+            self.action,
+            state_name(self.previous),
+            state_name(self.new)
+        )
+    }
+}
+
+pub fn state_name(auto_hide: bool) -> &'static str {
+    if auto_hide { "hidden" } else { "shown" }
+}
+
+/// Appends an action to the local event history. Nothing leaves the machine.
+pub fn record(event: &Event) -> Result<()> {
+    let path = paths::events_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+    }
+    // One `write` per line keeps lines from concurrent tb processes whole.
+    let line = format!("{}\n", facet_json::to_string(event));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
+
+/// Every recorded event, oldest first. Lines that cannot be parsed are skipped.
+pub fn load() -> Result<Vec<Event>> {
+    let path = paths::events_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let mut events = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| facet_json::from_str::<Event>(line).ok())
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Time of the first event, in Unix milliseconds.
+    pub since: Option<u64>,
+    /// State changes per UTC day, oldest first.
+    pub per_day: Vec<(String, usize)>,
+    pub hidden: Duration,
+    pub shown: Duration,
+    /// Actions per source, most used first.
+    pub triggers: Vec<(String, usize)>,
+}
+
+/// Summarizes `events` (oldest first) up to `now`, in Unix milliseconds.
+///
+/// Time before the first event is unknown and not counted; after each event the taskbar is taken
+/// to stay in the state it left until the next one.
+pub fn summarize(events: &[Event], now: u64) -> Stats {
+    let mut per_day = BTreeMap::<u64, usize>::new();
+    let mut triggers = HashMap::<&str, usize>::new();
+    let mut hidden = 0;
+    let mut shown = 0;
+    for (index, event) in events.iter().enumerate() {
+        *triggers.entry(event.source.as_str()).or_default() += 1;
+        if event.is_transition() {
+            *per_day.entry(event.time / MILLIS_PER_DAY).or_default() += 1;
+        }
+        let until = events.get(index + 1).map_or(now, |next| next.time);
+        let span = until.saturating_sub(event.time);
+        if event.new {
+            hidden += span;
+        } else {
+            shown += span;
+        }
+    }
+
+    let mut triggers = triggers
+        .into_iter()
+        .map(|(source, count)| (source.to_string(), count))
+        .collect::<Vec<_>>();
+    triggers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Stats {
+        since: events.first().map(|event| event.time),
+        per_day: per_day
+            .into_iter()
+            .map(|(day, count)| (format_date(day * MILLIS_PER_DAY), count))
+            .collect(),
+        hidden: Duration::from_millis(hidden),
+        shown: Duration::from_millis(shown),
+        triggers,
+    }
+}
+
+pub fn now_millis() -> u64 {
+    u64::try_from(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis(),
+    )
+    .unwrap_or(u64::MAX)
+}
+
+/// `YYYY-MM-DD` in UTC for Unix milliseconds.
+pub fn format_date(millis: u64) -> String {
+    // Howard Hinnant's `civil_from_days`.
+    let days = millis / MILLIS_PER_DAY + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC for Unix milliseconds.
+pub fn format_time(millis: u64) -> String {
+    let seconds = millis / 1000 % 86_400;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(millis),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// A short duration such as `3d 4h 12m`, `5m` or `40s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    let mut parts = Vec::new();
+    if days > 0 {
+        parts.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if minutes > 0 {
+        parts.push(format!("{minutes}m"));
+    }
+    if parts.is_empty() {
+        parts.push(format!("{seconds}s"));
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    fn event(time: u64, source: &str, previous: bool, new: bool) -> Event {
+        Event {
+            time,
+            action: "toggle".to_string(),
+            source: source.to_string(),
+            previous,
+            new,
+        }
+    }
+
+    #[test]
+    fn format_dates() {
+        let cases = [
+            (0, "1970-01-01"),
+            (951_782_400_000, "2000-02-29"),
+            (1_709_251_199_999, "2024-02-29"),
+            (1_709_251_200_000, "2024-03-01"),
+            (253_402_300_799_000, "9999-12-31"),
+        ];
+        for (millis, date) in cases {
+            assert_eq!(format_date(millis), date, "{millis}");
+        }
+        assert_eq!(format_time(1_709_251_199_999), "2024-02-29 23:59:59");
+    }
+
+    #[test]
+    fn summarize_events() {
+        let day = 1_709_164_800_000;
+        let events = [
+            event(day + HOUR, "cli", false, true),
+            event(day + 3 * HOUR, "hotkey", true, false),
+            event(day + MILLIS_PER_DAY + HOUR, "hotkey", false, true),
+            event(day + MILLIS_PER_DAY + 2 * HOUR, "menu", true, true),
+        ];
+        let stats = summarize(&events, day + MILLIS_PER_DAY + 4 * HOUR);
+
+        assert_eq!(stats.since, Some(day + HOUR));
+        assert_eq!(
+            stats.per_day,
+            [("2024-02-29".to_string(), 2), ("2024-03-01".to_string(), 1)]
+        );
+        assert_eq!(stats.hidden, Duration::from_millis(5 * HOUR));
+        assert_eq!(stats.shown, Duration::from_millis(22 * HOUR));
+        assert_eq!(
+            stats.triggers,
+            [
+                ("hotkey".to_string(), 2),
+                ("cli".to_string(), 1),
+                ("menu".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn summarize_nothing() {
+        let stats = summarize(&[], 1_000);
+        assert_eq!(stats.since, None);
+        assert!(stats.per_day.is_empty());
+        assert_eq!(stats.hidden + stats.shown, Duration::ZERO);
+        assert!(stats.triggers.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::action::{Action, Source};
+use crate::events::Event;
+use crate::paths;
+use crate::store::{self, FileLock};
+use eyre::{Context, Result, eyre};
+use facet::Facet;
+use std::path::PathBuf;
+
+/// How many changes [`paths::STATE_FILE`] keeps for `tb history` and `tb undo`.
+pub const HISTORY_LIMIT: usize = 50;
+const STATE_LOCK_FILE: &str = "state.json.lock";
+
+#[derive(Facet, Debug, Default)]
+#[facet(default)]
+struct State {
+    /// Taskbar changes, oldest first.
+    history: Vec<Event>,
+}
+
+/// Records a state change, dropping the oldest once there are more than [`HISTORY_LIMIT`].
+pub fn push(change: Event) -> Result<()> {
+    let lock = lock_state()?;
+    let mut state = load_state()?;
+    state.history.push(change);
+    let excess = state.history.len().saturating_sub(HISTORY_LIMIT);
+    state.history.drain(..excess);
+    save_state(&state, &lock)
+}
+
+/// Recorded changes, newest first.
+pub fn list() -> Result<Vec<Event>> {
+    let mut history = load_state()?.history;
+    history.reverse();
+    Ok(history)
+}
+
+/// Reverts the most recent change and removes it from the history.
+///
+/// Returns the reverted change and the resulting auto-hide state, or `None` when there is
+/// nothing to undo. The revert is not itself recorded, so repeated undos walk further back.
+pub fn undo() -> Result<Option<(Event, bool)>> {
+    let lock = lock_state()?;
+    let mut state = load_state()?;
+    let Some(change) = state.history.pop() else {
+        return Ok(None);
+    };
+    let enabled = Action::for_state(change.previous).perform(Source::Undo)?;
+    save_state(&state, &lock)?;
+    Ok(Some((change, enabled)))
+}
+
+fn lock_state() -> Result<FileLock> {
+    paths::app_home()?.ensure_dir()?;
+    FileLock::acquire(&state_lock_path()?)
+}
+
+fn state_lock_path() -> Result<PathBuf> {
+    Ok(paths::app_home()?.path().join(STATE_LOCK_FILE))
+}
+
+fn load_state() -> Result<State> {
+    let path = paths::state_path()?;
+    if !path.exists() {
+        return Ok(State::default());
+    }
+    let json = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    facet_json::from_str(&json).map_err(|error| eyre!("{}: {error}", path.display()))
+}
+
+fn save_state(state: &State, lock: &FileLock) -> Result<()> {
+    lock.ensure_guards(&state_lock_path()?)?;
+    store::write_atomic(
+        &paths::state_path()?,
+        facet_json::to_string(state).as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taskbar;
+
+    fn event(time: u64) -> Event {
+        Event {
+            time,
+            action: "toggle".to_string(),
+            source: "cli".to_string(),
+            previous: false,
+            new: true,
+        }
+    }
+
+    #[test]
+    fn push_keeps_the_newest_changes() {
+        let _dirs = paths::scratch_dirs();
+        let limit = u64::try_from(HISTORY_LIMIT).unwrap();
+        for time in 0..limit + 5 {
+            push(event(time)).unwrap();
+        }
+
+        let times = list()
+            .unwrap()
+            .iter()
+            .map(|change| change.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, (5..limit + 5).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn undo_reverts_the_latest_change_once() {
+        let _dirs = paths::scratch_dirs();
+        taskbar::set_taskbar_auto_hide(false).unwrap();
+        Action::Enable.perform(Source::Menu).unwrap();
+        Action::Enable.perform(Source::Menu).unwrap();
+        assert_eq!(list().unwrap().len(), 1, "only changes are recorded");
+
+        let (change, enabled) = undo().unwrap().unwrap();
+        assert_eq!(
+            (change.action.as_str(), change.source.as_str()),
+            ("enable", "menu")
+        );
+        assert!(!enabled);
+        assert!(!taskbar::is_taskbar_auto_hide_enabled().unwrap());
+        assert!(list().unwrap().is_empty(), "the revert is not recorded");
+        assert!(undo().unwrap().is_none());
+    }
+}
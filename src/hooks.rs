@@ -0,0 +1,287 @@
+use crate::action::{Action, Source};
+use crate::config::HooksConfig;
+use crate::events::state_name;
+use eyre::{Context, Result};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(windows)]
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+#[cfg(windows)]
+use windows::Win32::Foundation::HANDLE;
+#[cfg(windows)]
+use windows::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, TerminateJobObject,
+};
+#[cfg(windows)]
+use windows::Win32::System::Threading::CREATE_NO_WINDOW;
+#[cfg(windows)]
+use windows::core::PCWSTR;
+
+/// How often a running hook is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static HOOKS: OnceLock<HooksConfig> = OnceLock::new();
+/// Threads of hooks that may still be running, joined by [`wait`].
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Sets the hooks for this process; until it is called no hook runs.
+pub fn configure(hooks: HooksConfig) {
+    let _ = HOOKS.set(hooks);
+}
+
+/// Blocks until every hook started so far has exited or been killed for its timeout.
+pub fn wait() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(PoisonError::into_inner));
+    if !pending.is_empty() {
+        tracing::debug!("Waiting for {} hook(s) to finish", pending.len());
+    }
+    for handle in pending {
+        let _ = handle.join();
+    }
+}
+
+/// Calls [`wait`] when dropped, so a short-lived command such as `tb toggle` does not exit while
+/// its hooks are still running.
+#[derive(Debug)]
+pub struct WaitOnDrop;
+
+impl Drop for WaitOnDrop {
+    fn drop(&mut self) {
+        wait();
+    }
+}
+
+/// Runs `on_hide` or `on_show` after `action` moved auto-hide from `previous` to `new`.
+pub fn changed(action: Action, source: Source, previous: bool, new: bool) {
+    let Some(hooks) = HOOKS.get() else {
+        return;
+    };
+    let (name, command) = if new {
+        ("on_hide", &hooks.on_hide)
+    } else {
+        ("on_show", &hooks.on_show)
+    };
+    if let Some(command) = command {
+        let env = vec![
+            ("TB_HOOK", name.to_string()),
+            ("TB_ACTION", action.name().to_string()),
+            ("TB_SOURCE", source.name().to_string()),
+            ("TB_PREVIOUS_STATE", state_name(previous).to_string()),
+            ("TB_NEW_STATE", state_name(new).to_string()),
+        ];
+        spawn(name, command, hooks.timeout_seconds, env);
+    }
+}
+
+/// Runs `on_toggle_failed` after `action` failed with `error`.
+pub fn failed(action: Action, source: Source, error: &eyre::Report) {
+    let Some(hooks) = HOOKS.get() else {
+        return;
+    };
+    if let Some(command) = &hooks.on_toggle_failed {
+        let env = vec![
+            ("TB_HOOK", "on_toggle_failed".to_string()),
+            ("TB_ACTION", action.name().to_string()),
+            ("TB_SOURCE", source.name().to_string()),
+            ("TB_ERROR", format!("{error:#}")),
+        ];
+        spawn("on_toggle_failed", command, hooks.timeout_seconds, env);
+    }
+}
+
+/// Starts `command` on its own thread, so a slow hook never holds up the tray's message loop.
+fn spawn(
+    name: &'static str,
+    command: &str,
+    timeout_seconds: u32,
+    env: Vec<(&'static str, String)>,
+) {
+    let command = command.to_string();
+    let timeout = (timeout_seconds > 0).then(|| Duration::from_secs(timeout_seconds.into()));
+    let spawned = std::thread::Builder::new()
+        .name(format!("tb-hook-{name}"))
+        .spawn(move || match run(&command, timeout, env) {
+            Ok(Some(status)) if status.success() => {
+                tracing::info!("Hook {name} exited with {status}");
+            }
+            Ok(Some(status)) => tracing::warn!("Hook {name} exited with {status}"),
+            Ok(None) => {
+                tracing::warn!(
+                    "Hook {name} was killed after running for {timeout_seconds} seconds"
+                );
+            }
+            Err(error) => tracing::warn!("Hook {name} failed: {error:#}"),
+        });
+    match spawned {
+        Ok(handle) => {
+            let mut pending = PENDING.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.retain(|handle| !handle.is_finished());
+            pending.push(handle);
+        }
+        Err(error) => tracing::warn!("Failed to start a thread for hook {name}: {error}"),
+    }
+}
+
+/// Runs `command` through the shell (`cmd /C` on Windows, `sh -c` elsewhere) and waits for it.
+/// Returns `None` when it was killed, along with anything it started, for outliving `timeout`.
+fn run(
+    command: &str,
+    timeout: Option<Duration>,
+    env: Vec<(&'static str, String)>,
+) -> Result<Option<ExitStatus>> {
+    tracing::debug!("Running hook: {command}");
+    let child = shell(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .wrap_err_with(|| format!("Failed to start '{command}'"))?;
+    let mut hook = Running::new(child);
+
+    let Some(timeout) = timeout else {
+        return hook
+            .child
+            .wait()
+            .map(Some)
+            .wrap_err("Failed to wait for the hook");
+    };
+    let started = Instant::now();
+    loop {
+        if let Some(status) = hook
+            .child
+            .try_wait()
+            .wrap_err("Failed to wait for the hook")?
+        {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            hook.kill()?;
+            let _ = hook.child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// A hook's shell and whatever it starts: on Unix the process group [`shell`] makes it lead, on
+/// Windows a job object of its own. Killing only the shell would leave its children running.
+#[derive(Debug)]
+struct Running {
+    child: Child,
+    #[cfg(windows)]
+    job: Option<OwnedHandle>,
+}
+
+impl Running {
+    #[cfg(unix)]
+    fn new(child: Child) -> Self {
+        Self { child }
+    }
+
+    #[cfg(windows)]
+    fn new(child: Child) -> Self {
+        let job = contain(&child)
+            .inspect_err(|error| {
+                tracing::debug!("A timeout will only kill the hook's shell: {error}");
+            })
+            .ok();
+        Self { child, job }
+    }
+
+    #[cfg(unix)]
+    fn kill(&mut self) -> Result<()> {
+        let group = libc::pid_t::try_from(self.child.id()).wrap_err("Invalid hook process id")?;
+        // SAFETY: `kill` only sends a signal; the group is the hook's own, which it leads.
+        if unsafe { libc::kill(-group, libc::SIGKILL) } == 0 {
+            return Ok(());
+        }
+        self.child.kill().wrap_err("Failed to kill the hook")
+    }
+
+    #[cfg(windows)]
+    fn kill(&mut self) -> Result<()> {
+        if let Some(job) = &self.job {
+            // SAFETY: the job handle stays open until `self` is dropped.
+            if unsafe { TerminateJobObject(HANDLE(job.as_raw_handle()), 1) }.is_ok() {
+                return Ok(());
+            }
+        }
+        self.child.kill().wrap_err("Failed to kill the hook")
+    }
+}
+
+/// Puts `child` in a job object of its own, which is closed when the returned handle is dropped.
+#[cfg(windows)]
+fn contain(child: &Child) -> windows::core::Result<OwnedHandle> {
+    // SAFETY: creates an unnamed job with default security.
+    let job = unsafe { CreateJobObjectW(None, PCWSTR::null()) }?;
+    // SAFETY: the job handle was just created and nothing else owns it.
+    let job = unsafe { OwnedHandle::from_raw_handle(job.0) };
+    let process = HANDLE(child.as_raw_handle());
+    // SAFETY: both handles stay open for the duration of the call.
+    unsafe { AssignProcessToJobObject(HANDLE(job.as_raw_handle()), process) }?;
+    Ok(job)
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell
+        .arg("/C")
+        // Passed through untouched so the command is parsed exactly as typed at a prompt.
+        .raw_arg(command)
+        .creation_flags(CREATE_NO_WINDOW.0);
+    shell
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command).process_group(0);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    const SLOW: &str = "ping -n 6 127.0.0.1";
+    #[cfg(unix)]
+    const SLOW: &str = "sleep 5";
+
+    #[test]
+    fn run_kills_hooks_that_outlive_their_timeout() {
+        let started = Instant::now();
+        let status = run(SLOW, Some(Duration::from_millis(300)), Vec::new()).unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(status, None);
+        assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+    }
+
+    #[test]
+    fn run_reports_the_exit_status() {
+        let status = run("exit 3", Some(Duration::from_secs(5)), Vec::new()).unwrap();
+        assert_eq!(status.and_then(|status| status.code()), Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_kills_what_a_timed_out_hook_started() {
+        let late = std::env::temp_dir().join(format!("tb-hook-late-{}", std::process::id()));
+        let _ = std::fs::remove_file(&late);
+        let env = vec![("TB_LATE", late.display().to_string())];
+        let command = r#"(sleep 1; touch "$TB_LATE") & wait"#;
+        let status = run(command, Some(POLL_INTERVAL), env);
+        assert_eq!(status.unwrap(), None);
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!late.exists(), "the hook's background job outlived it");
+    }
+}
@@ -1,29 +1,79 @@
-use crate::paths;
-use eyre::{Context, Result, bail};
+use crate::keycode::{
+    MOD_ALT, MOD_CONTROL, MOD_SHIFT, MOD_WIN, VK_BACK, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE,
+    VK_F1, VK_F24, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT, VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SPACE,
+    VK_TAB, VK_UP,
+};
+#[cfg(windows)]
+use crate::keycode::{VK_CONTROL, VK_LWIN, VK_MENU, VK_SHIFT};
+use eyre::{Result, bail};
+#[cfg(windows)]
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_SHIFT, MOD_WIN, VK_BACK, VK_DELETE, VK_DOWN,
-    VK_END, VK_ESCAPE, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_F10,
-    VK_F11, VK_F12, VK_F13, VK_F14, VK_F15, VK_F16, VK_F17, VK_F18, VK_F19, VK_F20, VK_F21,
-    VK_F22, VK_F23, VK_F24, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT, VK_PRIOR, VK_RETURN,
-    VK_RIGHT, VK_SPACE, VK_TAB, VK_UP,
+    GetAsyncKeyState, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT,
+    KEYEVENTF_KEYUP, SendInput, VIRTUAL_KEY,
 };
 
-const HOTKEY_CONFIG_FILE: &str = "hotkey.txt";
-const DEFAULT_HOTKEY_EXPRESSION: &str = "Ctrl+Shift+B";
+pub const DEFAULT_HOTKEY_EXPRESSION: &str = "Ctrl+Shift+B";
+
+/// Prefix characters that mark an AutoHotkey-style expression such as `^+b` or `#!F12`.
+const AHK_PREFIXES: [char; 9] = ['^', '+', '!', '#', '<', '>', '*', '~', '$'];
+
+const NAMED_KEYS: &[(&[&str], u16, &str)] = &[
+    (&["space"], VK_SPACE, "Space"),
+    (&["tab"], VK_TAB, "Tab"),
+    (&["enter", "return"], VK_RETURN, "Enter"),
+    (&["esc", "escape"], VK_ESCAPE, "Escape"),
+    (&["backspace", "bksp", "bs"], VK_BACK, "Backspace"),
+    (&["insert", "ins"], VK_INSERT, "Insert"),
+    (&["delete", "del"], VK_DELETE, "Delete"),
+    (&["home"], VK_HOME, "Home"),
+    (&["end"], VK_END, "End"),
+    (&["pageup", "pgup"], VK_PRIOR, "PageUp"),
+    (&["pagedown", "pgdn"], VK_NEXT, "PageDown"),
+    (&["up", "arrowup"], VK_UP, "Up"),
+    (&["down", "arrowdown"], VK_DOWN, "Down"),
+    (&["left", "arrowleft"], VK_LEFT, "Left"),
+    (&["right", "arrowright"], VK_RIGHT, "Right"),
+];
 
 #[derive(Debug, Clone, Copy)]
 pub struct HotkeyRegistration {
-    pub modifiers: HOT_KEY_MODIFIERS,
+    /// `MOD_*` flags, as `RegisterHotKey` takes them.
+    #[cfg_attr(
+        not(windows),
+        expect(
+            dead_code,
+            reason = "only the tray, which builds on Windows alone, registers hotkeys"
+        )
+    )]
+    pub modifiers: u32,
+    #[cfg_attr(
+        not(any(windows, test)),
+        expect(
+            dead_code,
+            reason = "only the tray, which builds on Windows alone, registers hotkeys"
+        )
+    )]
     pub vk: u32,
 }
 
 #[derive(Debug, Clone)]
 pub struct Hotkey {
     pub expression: String,
+    #[cfg_attr(
+        not(any(windows, test)),
+        expect(
+            dead_code,
+            reason = "only the tray, which builds on Windows alone, registers hotkeys"
+        )
+    )]
     pub registration: HotkeyRegistration,
 }
 
 pub fn parse_hotkey_expression(expression: &str) -> Result<Hotkey> {
+    if expression.trim_start().starts_with(AHK_PREFIXES) {
+        return parse_ahk_expression(expression);
+    }
+
     let mut has_ctrl = false;
     let mut has_shift = false;
     let mut has_alt = false;
@@ -56,74 +106,137 @@ pub fn parse_hotkey_expression(expression: &str) -> Result<Hotkey> {
     }
 
     let (vk, key_label) = key.ok_or_else(|| eyre::eyre!("Hotkey key is missing"))?;
-    let modifiers = build_modifiers(has_ctrl, has_shift, has_alt, has_win);
+    Ok(build_hotkey(has_ctrl, has_shift, has_alt, has_win, vk, key_label))
+}
+
+/// Parses `AutoHotkey` hotkey notation: `^` Ctrl, `+` Shift, `!` Alt, `#` Win, followed by a key.
+///
+/// The `<`/`>` side selectors and the `*`, `~` and `$` options are accepted and ignored, since
+/// `RegisterHotKey` cannot distinguish them.
+pub fn parse_ahk_expression(expression: &str) -> Result<Hotkey> {
+    let mut has_ctrl = false;
+    let mut has_shift = false;
+    let mut has_alt = false;
+    let mut has_win = false;
+
+    let mut rest = expression.trim();
+    while rest.chars().count() > 1 {
+        let mut chars = rest.chars();
+        match chars.next() {
+            Some('^') => has_ctrl = true,
+            Some('+') => has_shift = true,
+            Some('!') => has_alt = true,
+            Some('#') => has_win = true,
+            Some('<' | '>' | '*' | '~' | '$') => {}
+            _ => break,
+        }
+        rest = chars.as_str();
+    }
+
+    if rest.is_empty() {
+        bail!("AutoHotkey expression '{expression}' is missing a key")
+    }
+    if rest.contains('&') {
+        bail!("AutoHotkey custom combinations are not supported: {expression}")
+    }
+    if rest.to_ascii_lowercase().ends_with(" up") {
+        bail!("AutoHotkey key-release hotkeys are not supported: {expression}")
+    }
+
+    let (vk, key_label) = parse_key_token(&rest.to_ascii_lowercase())?;
+    Ok(build_hotkey(has_ctrl, has_shift, has_alt, has_win, vk, key_label))
+}
+
+/// Builds a normalized hotkey from raw Win32 parts, e.g. the virtual-key lists `PowerToys` stores.
+#[expect(clippy::fn_params_excessive_bools, reason = "one flag per modifier, like `build_modifiers`")]
+pub fn hotkey_from_parts(ctrl: bool, shift: bool, alt: bool, win: bool, vk: u32) -> Result<Hotkey> {
+    let key_label =
+        key_label_for_vk(vk).ok_or_else(|| eyre::eyre!("Unsupported virtual key code: {vk}"))?;
+    Ok(build_hotkey(ctrl, shift, alt, win, vk, key_label))
+}
+
+#[expect(clippy::fn_params_excessive_bools, reason = "one flag per modifier, like `build_modifiers`")]
+fn build_hotkey(ctrl: bool, shift: bool, alt: bool, win: bool, vk: u32, key_label: String) -> Hotkey {
+    let modifiers = build_modifiers(ctrl, shift, alt, win);
 
     let mut parts = Vec::new();
-    if has_ctrl {
+    if ctrl {
         parts.push("Ctrl".to_string());
     }
-    if has_shift {
+    if shift {
         parts.push("Shift".to_string());
     }
-    if has_alt {
+    if alt {
         parts.push("Alt".to_string());
     }
-    if has_win {
+    if win {
         parts.push("Win".to_string());
     }
     parts.push(key_label);
 
-    Ok(Hotkey {
+    Hotkey {
         expression: parts.join("+"),
         registration: HotkeyRegistration { modifiers, vk },
-    })
-}
-
-pub fn load_hotkey() -> Result<Hotkey> {
-    let path = hotkey_path()?;
-    if !path.exists() {
-        return parse_hotkey_expression(DEFAULT_HOTKEY_EXPRESSION);
     }
+}
 
-    let raw = std::fs::read_to_string(&path).wrap_err_with(|| {
-        format!(
-            "Failed to read hotkey configuration at {}",
-            path.display()
-        )
-    })?;
-    let trimmed = raw.trim();
-    if trimmed.is_empty() {
-        return parse_hotkey_expression(DEFAULT_HOTKEY_EXPRESSION);
-    }
+/// Replays a hotkey's keystroke to the focused window.
+///
+/// The caller must unregister the hotkey first or the keystroke is swallowed again. Modifiers
+/// the user already released are pressed around the key so the target sees the full combination.
+#[cfg(windows)]
+pub fn send_keystroke(registration: HotkeyRegistration) -> Result<()> {
+    let modifier_keys = [
+        (MOD_CONTROL, VK_CONTROL),
+        (MOD_SHIFT, VK_SHIFT),
+        (MOD_ALT, VK_MENU),
+        (MOD_WIN, VK_LWIN),
+    ];
+    let missing = modifier_keys
+        .iter()
+        .filter(|(modifier, key)| {
+            registration.modifiers & modifier != 0
+                && unsafe { GetAsyncKeyState(i32::from(*key)) } >= 0
+        })
+        .map(|(_, key)| VIRTUAL_KEY(*key))
+        .collect::<Vec<_>>();
+    let key = VIRTUAL_KEY(u16::try_from(registration.vk)?);
 
-    parse_hotkey_expression(trimmed).wrap_err_with(|| {
-        format!(
-            "Invalid hotkey expression in {}: {trimmed}",
-            path.display()
-        )
-    })
-}
+    let mut inputs = missing
+        .iter()
+        .map(|modifier| key_input(*modifier, false))
+        .collect::<Vec<_>>();
+    inputs.push(key_input(key, false));
+    inputs.push(key_input(key, true));
+    inputs.extend(missing.iter().rev().map(|modifier| key_input(*modifier, true)));
 
-pub fn save_hotkey_expression(expression: &str) -> Result<Hotkey> {
-    let hotkey = parse_hotkey_expression(expression)?;
-    let path = hotkey_path()?;
-    std::fs::write(&path, format!("{}\n", hotkey.expression)).wrap_err_with(|| {
-        format!(
-            "Failed to write hotkey configuration at {}",
-            path.display()
-        )
-    })?;
-    Ok(hotkey)
+    let sent = unsafe { SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) };
+    if sent as usize != inputs.len() {
+        bail!("SendInput delivered {sent} of {} key events", inputs.len())
+    }
+    Ok(())
 }
 
-fn hotkey_path() -> Result<std::path::PathBuf> {
-    let home = paths::app_home()?;
-    home.ensure_dir()?;
-    Ok(home.path().join(HOTKEY_CONFIG_FILE))
+#[cfg(windows)]
+fn key_input(key: VIRTUAL_KEY, release: bool) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: key,
+                dwFlags: if release {
+                    KEYEVENTF_KEYUP
+                } else {
+                    KEYBD_EVENT_FLAGS(0)
+                },
+                ..Default::default()
+            },
+        },
+    }
 }
 
-fn build_modifiers(ctrl: bool, shift: bool, alt: bool, win: bool) -> HOT_KEY_MODIFIERS {
-    let mut modifiers = HOT_KEY_MODIFIERS(0);
+fn build_modifiers(ctrl: bool, shift: bool, alt: bool, win: bool) -> u32 {
+    let mut modifiers = 0;
     if ctrl {
         modifiers |= MOD_CONTROL;
     }
@@ -151,54 +264,92 @@ fn parse_key_token(token: &str) -> Result<(u32, String)> {
     if let Some(index) = token.strip_prefix('f').and_then(|value| value.parse::<u8>().ok())
         && (1..=24).contains(&index)
     {
-        let virtual_key = match index {
-            1 => u32::from(VK_F1.0),
-            2 => u32::from(VK_F2.0),
-            3 => u32::from(VK_F3.0),
-            4 => u32::from(VK_F4.0),
-            5 => u32::from(VK_F5.0),
-            6 => u32::from(VK_F6.0),
-            7 => u32::from(VK_F7.0),
-            8 => u32::from(VK_F8.0),
-            9 => u32::from(VK_F9.0),
-            10 => u32::from(VK_F10.0),
-            11 => u32::from(VK_F11.0),
-            12 => u32::from(VK_F12.0),
-            13 => u32::from(VK_F13.0),
-            14 => u32::from(VK_F14.0),
-            15 => u32::from(VK_F15.0),
-            16 => u32::from(VK_F16.0),
-            17 => u32::from(VK_F17.0),
-            18 => u32::from(VK_F18.0),
-            19 => u32::from(VK_F19.0),
-            20 => u32::from(VK_F20.0),
-            21 => u32::from(VK_F21.0),
-            22 => u32::from(VK_F22.0),
-            23 => u32::from(VK_F23.0),
-            24 => u32::from(VK_F24.0),
-            _ => unreachable!(),
-        };
+        let virtual_key = u32::from(VK_F1) + u32::from(index - 1);
         return Ok((virtual_key, format!("F{index}")));
     }
 
-    let (key, label) = match token {
-        "space" => (u32::from(VK_SPACE.0), "Space"),
-        "tab" => (u32::from(VK_TAB.0), "Tab"),
-        "enter" | "return" => (u32::from(VK_RETURN.0), "Enter"),
-        "esc" | "escape" => (u32::from(VK_ESCAPE.0), "Escape"),
-        "backspace" | "bksp" => (u32::from(VK_BACK.0), "Backspace"),
-        "insert" | "ins" => (u32::from(VK_INSERT.0), "Insert"),
-        "delete" | "del" => (u32::from(VK_DELETE.0), "Delete"),
-        "home" => (u32::from(VK_HOME.0), "Home"),
-        "end" => (u32::from(VK_END.0), "End"),
-        "pageup" | "pgup" => (u32::from(VK_PRIOR.0), "PageUp"),
-        "pagedown" | "pgdn" => (u32::from(VK_NEXT.0), "PageDown"),
-        "up" | "arrowup" => (u32::from(VK_UP.0), "Up"),
-        "down" | "arrowdown" => (u32::from(VK_DOWN.0), "Down"),
-        "left" | "arrowleft" => (u32::from(VK_LEFT.0), "Left"),
-        "right" | "arrowright" => (u32::from(VK_RIGHT.0), "Right"),
-        _ => bail!("Unsupported hotkey key token: {token}"),
-    };
-
-    Ok((key, label.to_string()))
-}
\ No newline at end of file
+    NAMED_KEYS
+        .iter()
+        .find(|(aliases, _, _)| aliases.contains(&token))
+        .map(|(_, key, label)| (u32::from(*key), (*label).to_string()))
+        .ok_or_else(|| eyre::eyre!("Unsupported hotkey key token: {token}"))
+}
+
+fn key_label_for_vk(vk: u32) -> Option<String> {
+    if let Some(character) = char::from_u32(vk)
+        && (character.is_ascii_digit() || character.is_ascii_uppercase())
+    {
+        return Some(character.to_string());
+    }
+
+    if (u32::from(VK_F1)..=u32::from(VK_F24)).contains(&vk) {
+        return Some(format!("F{}", vk - u32::from(VK_F1) + 1));
+    }
+
+    NAMED_KEYS
+        .iter()
+        .find(|(_, key, _)| u32::from(*key) == vk)
+        .map(|(_, _, label)| (*label).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ahk_expressions() {
+        let cases = [
+            ("^+b", "Ctrl+Shift+B", u32::from(b'B')),
+            ("^+B", "Ctrl+Shift+B", u32::from(b'B')),
+            ("#!F12", "Alt+Win+F12", 0x7B),
+            ("!f1", "Alt+F1", 0x70),
+            ("<^>!Space", "Ctrl+Alt+Space", 0x20),
+            ("*~$^Esc", "Ctrl+Escape", 0x1B),
+            ("#1", "Win+1", u32::from(b'1')),
+            ("  ^PgDn  ", "Ctrl+PageDown", 0x22),
+        ];
+        for (expression, normalized, vk) in cases {
+            let hotkey = parse_ahk_expression(expression).unwrap();
+            assert_eq!(hotkey.expression, normalized, "{expression:?}");
+            assert_eq!(hotkey.registration.vk, vk, "{expression:?}");
+        }
+    }
+
+    #[test]
+    fn parse_ahk_rejects_unsupported_expressions() {
+        let cases = ["", "^", "^+", "+", "a & b", "^b up", "^+Pause", "^ab"];
+        for expression in cases {
+            assert!(
+                parse_ahk_expression(expression).is_err(),
+                "{expression:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_hotkey_expressions() {
+        let cases = [
+            ("ctrl+shift+b", "Ctrl+Shift+B"),
+            ("Shift+Ctrl+B", "Ctrl+Shift+B"),
+            ("WIN + ALT + f12", "Alt+Win+F12"),
+            ("control+meta+del", "Ctrl+Win+Delete"),
+            ("f9", "F9"),
+            ("^+b", "Ctrl+Shift+B"),
+        ];
+        for (expression, normalized) in cases {
+            let hotkey = parse_hotkey_expression(expression).unwrap();
+            assert_eq!(hotkey.expression, normalized, "{expression:?}");
+        }
+    }
+
+    #[test]
+    fn parse_hotkey_rejects_malformed_expressions() {
+        let cases = ["", "ctrl+shift", "ctrl+a+b", "ctrl+f25", "ctrl+pause"];
+        for expression in cases {
+            assert!(
+                parse_hotkey_expression(expression).is_err(),
+                "{expression:?} should not parse"
+            );
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::action::{Action, Source};
+use crate::dispatch;
+use crate::paths;
+use crate::store;
+use eyre::{Context, Result, eyre};
+use facet::Facet;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Where `tb serve --http` listens unless `--bind` says otherwise.
+pub const DEFAULT_BIND: &str = "127.0.0.1:7878";
+/// Holds the bearer token clients must send, under the app home.
+pub const TOKEN_FILE: &str = "http.token";
+/// An event-stream comment is sent this often so disconnected clients are noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+type Subscribers = Arc<Mutex<Vec<Sender<bool>>>>;
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct StateBody {
+    auto_hide: Option<bool>,
+}
+
+#[derive(Facet, Debug)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Serves the REST API on `bind` until the process is stopped.
+///
+/// `GET /state`, `POST /toggle` and `POST /state` with `{"autoHide": bool}` answer with the
+/// resulting `{"autoHide": bool}`; `GET /events` is a server-sent event stream of changes. Every
+/// request needs `Authorization: Bearer <token>` (or `?token=` for `EventSource`), with the token
+/// from [`token_path`].
+///
+/// # Errors
+///
+/// Fails when `bind` is not a socket address or cannot be bound, or the token cannot be written.
+pub fn serve(bind: &str) -> Result<()> {
+    let address: SocketAddr = bind
+        .parse()
+        .wrap_err_with(|| format!("Invalid bind address '{bind}'"))?;
+    if !address.ip().is_loopback() {
+        tracing::warn!("Serving on {address}, which is reachable from other machines");
+    }
+    let token: Arc<str> = load_or_create_token()?.into();
+    let server =
+        Server::http(address).map_err(|error| eyre!("Failed to bind {address}: {error}"))?;
+    tracing::info!(
+        "Listening on http://{address}; the token is in {}",
+        token_path()?.display()
+    );
+
+    let subscribers = Subscribers::default();
+    let broadcast = Arc::clone(&subscribers);
+    dispatch::watch(move |auto_hide| {
+        broadcast
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|subscriber| subscriber.send(auto_hide).is_ok());
+        true
+    })?;
+
+    for request in server.incoming_requests() {
+        let token = Arc::clone(&token);
+        let subscribers = Arc::clone(&subscribers);
+        // Event streams stay open, so every request gets its own thread.
+        std::thread::spawn(move || handle(request, &token, &subscribers));
+    }
+    Ok(())
+}
+
+/// Where the API token is kept, next to the config.
+///
+/// # Errors
+///
+/// Fails when the app home cannot be resolved.
+pub fn token_path() -> Result<PathBuf> {
+    Ok(paths::app_home()?.path().join(TOKEN_FILE))
+}
+
+/// Reads the token, generating a random one on first use that only the current user can read.
+fn load_or_create_token() -> Result<String> {
+    let path = token_path()?;
+    if let Ok(token) = std::fs::read_to_string(&path)
+        && !token.trim().is_empty()
+    {
+        return Ok(token.trim().to_string());
+    }
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|error| eyre!("Failed to generate a token: {error}"))?;
+    let token = bytes.iter().fold(String::new(), |mut token, byte| {
+        let _ = write!(token, "{byte:02x}");
+        token
+    });
+    paths::app_home()?.ensure_dir()?;
+    store::write_private(&path, token.as_bytes())?;
+    Ok(token)
+}
+
+fn handle(mut request: Request, token: &str, subscribers: &Subscribers) {
+    if !authorized(&request, token) {
+        respond_error(request, 401, "Missing or invalid token");
+        return;
+    }
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let result = match (&method, path.as_str()) {
+        (Method::Get, "/state") => dispatch::state(),
+        (Method::Post, "/toggle") => dispatch::perform(Action::Toggle, Source::Api),
+        (Method::Post, "/state") => {
+            let mut body = String::new();
+            if let Err(error) = request.as_reader().read_to_string(&mut body) {
+                respond_error(request, 400, &format!("Failed to read the body: {error}"));
+                return;
+            }
+            match facet_json::from_str::<StateBody>(&body) {
+                Ok(StateBody {
+                    auto_hide: Some(auto_hide),
+                }) => dispatch::perform(Action::for_state(auto_hide), Source::Api),
+                Ok(_) => {
+                    respond_error(request, 400, "Expected {\"autoHide\": true|false}");
+                    return;
+                }
+                Err(error) => {
+                    respond_error(request, 400, &format!("Invalid body: {error}"));
+                    return;
+                }
+            }
+        }
+        (Method::Get, "/events") => {
+            stream_events(request, subscribers);
+            return;
+        }
+        (_, "/state" | "/toggle" | "/events") => {
+            respond_error(request, 405, "Method not allowed");
+            return;
+        }
+        _ => {
+            respond_error(request, 404, "Not found");
+            return;
+        }
+    };
+    match result {
+        Ok(auto_hide) => respond_json(request, 200, &state_json(auto_hide)),
+        Err(error) => respond_error(request, 500, &format!("{error:#}")),
+    }
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let header = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .map(str::trim);
+    let query = request
+        .url()
+        .split_once('?')
+        .and_then(|(_, query)| query.split('&').find_map(|pair| pair.strip_prefix("token=")));
+    header
+        .or(query)
+        .is_some_and(|candidate| constant_time_eq(candidate.as_bytes(), token.as_bytes()))
+}
+
+/// Compares without returning early, so response timing does not reveal the token.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Writes the current state, then every change, until the client disconnects.
+fn stream_events(request: Request, subscribers: &Subscribers) {
+    let (sender, receiver) = mpsc::channel();
+    subscribers
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(sender);
+    let initial = dispatch::state().ok();
+
+    // tiny_http buffers chunked bodies, so the stream is written to the socket directly.
+    let mut writer = request.into_writer();
+    let mut send = |chunk: &str| {
+        writer
+            .write_all(chunk.as_bytes())
+            .and_then(|()| writer.flush())
+    };
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+                Connection: close\r\n\r\n";
+    if send(head).is_err() {
+        return;
+    }
+    if let Some(auto_hide) = initial
+        && send(&event(auto_hide)).is_err()
+    {
+        return;
+    }
+    loop {
+        let chunk = match receiver.recv_timeout(KEEPALIVE) {
+            Ok(auto_hide) => event(auto_hide),
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if send(&chunk).is_err() {
+            // Dropping the receiver unsubscribes on the next broadcast.
+            return;
+        }
+    }
+}
+
+fn event(auto_hide: bool) -> String {
+    format!("event: state\ndata: {}\n\n", state_json(auto_hide))
+}
+
+fn state_json(auto_hide: bool) -> String {
+    facet_json::to_string(&StateBody {
+        auto_hide: Some(auto_hide),
+    })
+}
+
+fn respond_error(request: Request, status: u16, error: &str) {
+    let body = facet_json::to_string(&ErrorBody {
+        error: error.to_string(),
+    });
+    respond_json(request, status, &body);
+}
+
+fn respond_json(request: Request, status: u16, body: &str) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(error) = request.respond(response) {
+        tracing::debug!("Failed to send an HTTP response: {error}");
+    }
+}
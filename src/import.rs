@@ -0,0 +1,412 @@
+use crate::action::Action;
+use crate::hotkey;
+use crate::keycode::{
+    VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MENU, VK_RCONTROL, VK_RMENU,
+    VK_RSHIFT, VK_RWIN, VK_SHIFT,
+};
+use crate::keymap::{Binding, Trigger};
+use eyre::{Context, Result, bail};
+use facet::Facet;
+use std::path::Path;
+
+/// `PowerToys` Keyboard Manager `operationType` for "Run program" shortcuts.
+const POWERTOYS_RUN_PROGRAM: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    AutoHotkey,
+    PowerToys,
+}
+
+impl ImportSource {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ahk" | "autohotkey" => Ok(ImportSource::AutoHotkey),
+            "powertoys" | "pt" => Ok(ImportSource::PowerToys),
+            other => bail!("Unknown import source '{other}', expected 'ahk' or 'powertoys'"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub bindings: Vec<Binding>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl ImportReport {
+    fn skip(&mut self, entry: impl Into<String>, reason: impl Into<String>) {
+        self.skipped.push(SkippedEntry {
+            entry: entry.into(),
+            reason: reason.into(),
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct SkippedEntry {
+    pub entry: String,
+    pub reason: String,
+}
+
+pub fn import_file(source: ImportSource, path: &Path) -> Result<ImportReport> {
+    let raw = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    match source {
+        ImportSource::AutoHotkey => Ok(import_ahk(&raw)),
+        ImportSource::PowerToys => import_powertoys(&raw)
+            .wrap_err_with(|| format!("Failed to parse PowerToys config {}", path.display())),
+    }
+}
+
+/// Extracts `hotkey::` definitions from an `AutoHotkey` v1 or v2 script.
+///
+/// A binding is translated when its body runs `tb <action>` or calls `SHAppBarMessage` (the
+/// usual AHK taskbar auto-hide snippet); anything else has no tb equivalent and is reported.
+fn import_ahk(script: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+    let lines = script.lines().map(strip_ahk_comment).collect::<Vec<_>>();
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim();
+        index += 1;
+
+        if line.starts_with(':') && line.contains("::") {
+            report.skip(line, "hotstrings have no tb equivalent");
+            continue;
+        }
+        let Some((trigger, inline_body)) = line.split_once("::") else {
+            continue;
+        };
+
+        // A body is the rest of the line, a `{ … }` block (tracked by depth, so nested blocks do
+        // not end it early) or, in v1 style, the lines up to `return`.
+        let mut depth = brace_depth(inline_body);
+        let mut body = inline_body.trim().trim_start_matches('{').to_string();
+        if body.trim().is_empty() || depth > 0 {
+            while index < lines.len() {
+                let next = lines[index].trim();
+                if depth == 0 && is_ahk_hotkey_line(next) {
+                    break;
+                }
+                index += 1;
+                if depth == 0 && next.eq_ignore_ascii_case("return") {
+                    break;
+                }
+                let before = depth;
+                depth += brace_depth(next);
+                if depth <= 0 && (before > 0 || next == "}") {
+                    break;
+                }
+                body.push('\n');
+                body.push_str(next);
+            }
+        }
+
+        let hotkey = match hotkey::parse_ahk_expression(trigger) {
+            Ok(hotkey) => hotkey,
+            Err(error) => {
+                report.skip(trigger, error.to_string());
+                continue;
+            }
+        };
+        match ahk_body_action(&body) {
+            Some(action) => report.bindings.push(Binding {
+                trigger: Trigger::Hotkey(hotkey),
+                action,
+                condition: None,
+            }),
+            None => report.skip(trigger, "action has no tb equivalent"),
+        }
+    }
+
+    report
+}
+
+fn strip_ahk_comment(line: &str) -> &str {
+    if line.trim_start().starts_with(';') {
+        return "";
+    }
+    match line.find(" ;").or_else(|| line.find("\t;")) {
+        Some(position) => &line[..position],
+        None => line,
+    }
+}
+
+/// Braces opened minus braces closed on `line`.
+fn brace_depth(line: &str) -> i32 {
+    line.chars().fold(0, |depth, character| match character {
+        '{' => depth + 1,
+        '}' => depth - 1,
+        _ => depth,
+    })
+}
+
+fn is_ahk_hotkey_line(line: &str) -> bool {
+    line.contains("::") && !line.starts_with(':')
+}
+
+fn ahk_body_action(body: &str) -> Option<Action> {
+    let lower = body.to_ascii_lowercase();
+    if lower.contains("shappbarmessage") {
+        return Some(Action::Toggle);
+    }
+    if !lower.contains("run") {
+        return None;
+    }
+
+    let tokens = lower
+        .split(|character: char| {
+            character.is_whitespace() || matches!(character, '"' | '\'' | ',' | '(' | ')')
+        })
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>();
+    tokens
+        .windows(2)
+        .find(|pair| is_tb_program(pair[0]))
+        .and_then(|pair| Action::parse(pair[1]).ok())
+}
+
+fn is_tb_program(program: &str) -> bool {
+    let name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+    name.eq_ignore_ascii_case("tb") || name.eq_ignore_ascii_case("tb.exe")
+}
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct KeyboardManagerConfig {
+    remap_keys: KeyRemaps,
+    remap_shortcuts: ShortcutRemaps,
+}
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct KeyRemaps {
+    in_process: Vec<ShortcutRemap>,
+}
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct ShortcutRemaps {
+    global: Vec<ShortcutRemap>,
+    app_specific: Vec<ShortcutRemap>,
+}
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct ShortcutRemap {
+    original_keys: String,
+    operation_type: u32,
+    run_program_file_path: String,
+    run_program_args: String,
+    target_app: String,
+}
+
+/// Extracts "Run program" shortcuts that launch tb from a Keyboard Manager `default.json`.
+fn import_powertoys(json: &str) -> Result<ImportReport> {
+    let config: KeyboardManagerConfig =
+        facet_json::from_str(json).map_err(|error| eyre::eyre!("{error}"))?;
+    let mut report = ImportReport::default();
+
+    for remap in &config.remap_keys.in_process {
+        report.skip(&remap.original_keys, "single-key remaps have no tb equivalent");
+    }
+    for remap in &config.remap_shortcuts.app_specific {
+        report.skip(
+            &remap.original_keys,
+            format!("app-specific shortcut for '{}' is not supported", remap.target_app),
+        );
+    }
+
+    for remap in &config.remap_shortcuts.global {
+        let hotkey = match powertoys_hotkey(&remap.original_keys) {
+            Ok(hotkey) => hotkey,
+            Err(error) => {
+                report.skip(&remap.original_keys, error.to_string());
+                continue;
+            }
+        };
+
+        if remap.operation_type != POWERTOYS_RUN_PROGRAM
+            || !is_tb_program(remap.run_program_file_path.trim_matches('"'))
+        {
+            report.skip(&hotkey.expression, "action has no tb equivalent");
+            continue;
+        }
+
+        let argument = remap.run_program_args.split_whitespace().next().unwrap_or("");
+        match Action::parse(argument) {
+            Ok(action) => report.bindings.push(Binding {
+                trigger: Trigger::Hotkey(hotkey),
+                action,
+                condition: None,
+            }),
+            Err(error) => report.skip(&hotkey.expression, error.to_string()),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Converts a `PowerToys` `originalKeys` value (`;`-separated virtual-key codes) into a hotkey.
+fn powertoys_hotkey(original_keys: &str) -> Result<hotkey::Hotkey> {
+    let mut ctrl = false;
+    let mut shift = false;
+    let mut alt = false;
+    let mut win = false;
+    let mut key = None;
+
+    for code in original_keys.split(';').filter(|code| !code.trim().is_empty()) {
+        let vk = code
+            .trim()
+            .parse::<u16>()
+            .wrap_err_with(|| format!("Invalid virtual key code '{code}'"))?;
+        match vk {
+            vk if [VK_CONTROL, VK_LCONTROL, VK_RCONTROL].contains(&vk) => ctrl = true,
+            vk if [VK_SHIFT, VK_LSHIFT, VK_RSHIFT].contains(&vk) => shift = true,
+            vk if [VK_MENU, VK_LMENU, VK_RMENU].contains(&vk) => alt = true,
+            vk if [VK_LWIN, VK_RWIN].contains(&vk) => win = true,
+            vk => {
+                if key.is_some() {
+                    bail!("Shortcut must contain exactly one non-modifier key")
+                }
+                key = Some(u32::from(vk));
+            }
+        }
+    }
+
+    let vk = key.ok_or_else(|| eyre::eyre!("Shortcut key is missing"))?;
+    hotkey::hotkey_from_parts(ctrl, shift, alt, win, vk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imported(report: &ImportReport) -> Vec<String> {
+        report.bindings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn import_ahk_bindings() {
+        let cases = [
+            ("^+b::Run tb toggle", vec!["Ctrl+Shift+B = toggle"]),
+            ("#h::Run, tb.exe enable ; hide it", vec!["Win+H = enable"]),
+            (
+                "^+b::\n    Run \"C:\\tools\\tb.exe\" disable\nreturn",
+                vec!["Ctrl+Shift+B = disable"],
+            ),
+            (
+                "^+b:: {\n    DllCall(\"Shell32\\SHAppBarMessage\", \"UInt\", 10)\n}",
+                vec!["Ctrl+Shift+B = toggle"],
+            ),
+            (
+                "^!t::\n{\n    Run \"tb toggle\"\n}\n^!e::Run tb enable",
+                vec!["Ctrl+Alt+T = toggle", "Ctrl+Alt+E = enable"],
+            ),
+            ("; ^+b::Run tb toggle", vec![]),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(imported(&import_ahk(script)), expected, "{script:?}");
+        }
+    }
+
+    #[test]
+    fn import_ahk_keeps_nested_blocks_in_the_body() {
+        let script = "^+b::\n{\n    if WinActive(\"ahk_exe game.exe\") {\n        Send \"^+b\"\n    }\n    Run \"tb disable\"\n}\n^+n::Run tb enable";
+        let report = import_ahk(script);
+        assert_eq!(
+            imported(&report),
+            ["Ctrl+Shift+B = disable", "Ctrl+Shift+N = enable"]
+        );
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+    }
+
+    #[test]
+    fn import_ahk_reports_what_it_skips() {
+        let script = "::btw::by the way\n^+b::Send \"hello\"\n^ab::Run tb toggle";
+        let report = import_ahk(script);
+        assert!(report.bindings.is_empty());
+        let skipped = report
+            .skipped
+            .iter()
+            .map(|skipped| skipped.entry.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, ["::btw::by the way", "^+b", "^ab"]);
+    }
+
+    #[test]
+    fn strip_ahk_comments() {
+        let cases = [
+            ("; a comment", ""),
+            ("   ;indented", ""),
+            ("^b::Run tb toggle ; trailing", "^b::Run tb toggle"),
+            ("^b::Run tb toggle\t; tab", "^b::Run tb toggle"),
+            ("Send \"a;b\"", "Send \"a;b\""),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(strip_ahk_comment(line), expected, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn ahk_body_actions() {
+        let cases = [
+            ("Run tb toggle", Some(Action::Toggle)),
+            ("Run, tb.exe hide", Some(Action::Enable)),
+            ("Run(\"C:\\bin\\tb.exe show\")", Some(Action::Disable)),
+            ("Run \"tb\" \"off\"", Some(Action::Disable)),
+            ("DllCall(\"SHAppBarMessage\")", Some(Action::Toggle)),
+            ("Run notepad.exe", None),
+            ("Run tb frobnicate", None),
+            ("Send {Enter}", None),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(ahk_body_action(body), expected, "{body:?}");
+        }
+    }
+
+    #[test]
+    fn powertoys_hotkeys() {
+        let cases = [
+            ("17;16;66", "Ctrl+Shift+B"),
+            ("162;161;66", "Ctrl+Shift+B"),
+            ("91;18;123", "Alt+Win+F12"),
+            ("165;32", "Alt+Space"),
+            ("112", "F1"),
+        ];
+        for (keys, expected) in cases {
+            let hotkey = powertoys_hotkey(keys).unwrap();
+            assert_eq!(hotkey.expression, expected, "{keys:?}");
+        }
+        for keys in ["", "17;16", "17;65;66", "17;x", "17;255"] {
+            assert!(powertoys_hotkey(keys).is_err(), "{keys:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn import_powertoys_run_program_shortcuts() {
+        let json = r#"{
+            "remapKeys": {"inProcess": [{"originalKeys": "20", "newRemapKeys": "27"}]},
+            "remapShortcuts": {
+                "global": [
+                    {"originalKeys": "17;16;66", "operationType": 1,
+                     "runProgramFilePath": "\"C:\\tools\\tb.exe\"", "runProgramArgs": "toggle"},
+                    {"originalKeys": "17;16;78", "operationType": 1,
+                     "runProgramFilePath": "notepad.exe", "runProgramArgs": ""},
+                    {"originalKeys": "17;16;77", "operationType": 0}
+                ],
+                "appSpecific": [{"originalKeys": "17;66", "targetApp": "code.exe"}]
+            }
+        }"#;
+        let report = import_powertoys(json).unwrap();
+        assert_eq!(imported(&report), ["Ctrl+Shift+B = toggle"]);
+        let skipped = report
+            .skipped
+            .iter()
+            .map(|skipped| skipped.entry.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, ["20", "17;66", "Ctrl+Shift+N", "Ctrl+Shift+M"]);
+    }
+}
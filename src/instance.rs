@@ -0,0 +1,65 @@
+use eyre::{Context, Result, bail};
+use std::time::Duration;
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_ALREADY_EXISTS, GetLastError, HANDLE, WAIT_ABANDONED, WAIT_OBJECT_0,
+};
+use windows::Win32::System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject};
+use windows::core::HSTRING;
+
+/// How long `--replace` waits for the running tray to exit.
+pub const REPLACE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ownership of the per-user tray mutex, held for as long as this process runs the tray.
+///
+/// A second `tb` finds the mutex taken instead of starting another tray whose hotkeys would fail
+/// to register.
+#[derive(Debug)]
+pub struct InstanceGuard(HANDLE);
+
+impl InstanceGuard {
+    /// Takes the mutex, or returns `None` when another tray in this session already holds it.
+    pub fn try_acquire() -> Result<Option<Self>> {
+        let handle = create_mutex()?;
+        if unsafe { GetLastError() } == ERROR_ALREADY_EXISTS {
+            let _ = unsafe { CloseHandle(handle) };
+            return Ok(None);
+        }
+        Ok(Some(Self(handle)))
+    }
+
+    /// Waits up to `timeout` for the tray holding the mutex to exit, then takes it over.
+    pub fn wait(timeout: Duration) -> Result<Self> {
+        let handle = create_mutex()?;
+        if unsafe { GetLastError() } != ERROR_ALREADY_EXISTS {
+            return Ok(Self(handle));
+        }
+        let millis = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        match unsafe { WaitForSingleObject(handle, millis) } {
+            // Abandoned means the previous tray exited without releasing it, which is fine.
+            WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(Self(handle)),
+            _ => {
+                let _ = unsafe { CloseHandle(handle) };
+                bail!(
+                    "The running tray did not exit within {} seconds",
+                    timeout.as_secs()
+                )
+            }
+        }
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let _ = unsafe { ReleaseMutex(self.0) };
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Opens or creates the mutex, owning it only if it did not exist yet.
+fn create_mutex() -> Result<HANDLE> {
+    // `Local\` scopes the name to this logon session and the user name to this user, matching
+    // the taskbar a tray can control.
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    let name = HSTRING::from(format!("Local\\tb-tray-{user}"));
+    unsafe { CreateMutexW(None, true, &name) }.wrap_err("Failed to create the tray instance mutex")
+}
@@ -0,0 +1,375 @@
+use crate::action::{Action, Source};
+use crate::events::Event;
+#[cfg(unix)]
+use crate::paths;
+use crate::{history, loglevel, taskbar};
+use eyre::{Context, Result, bail, eyre};
+use facet::Facet;
+#[cfg(unix)]
+use interprocess::local_socket::GenericFilePath;
+#[cfg(windows)]
+use interprocess::local_socket::GenericNamespaced;
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{ListenerOptions, Name, Stream};
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Bumped when a request or response changes incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long [`send`] waits for the running tray to answer.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One line of JSON sent to the tray's control endpoint.
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct Request {
+    pub version: u32,
+    /// `status`, `toggle`, `enable`, `disable`, `undo`, `set_log_filter`, `info` or `ping`.
+    pub method: String,
+    /// What asked for the change, by [`Source::name`]; `cli` when absent.
+    pub source: Option<String>,
+    /// Log filter directives for `set_log_filter`.
+    pub filter: Option<String>,
+}
+
+impl Request {
+    #[must_use]
+    pub fn new(method: &str) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            method: method.to_string(),
+            source: None,
+            filter: None,
+        }
+    }
+
+    /// Attributes the change to `source` instead of the CLI.
+    #[must_use]
+    pub fn with_source(self, source: Source) -> Self {
+        Self {
+            source: Some(source.name().to_string()),
+            ..self
+        }
+    }
+
+    /// A `set_log_filter` request for `directives`.
+    #[must_use]
+    pub fn set_log_filter(directives: &str) -> Self {
+        Self {
+            filter: Some(directives.trim().to_string()),
+            ..Self::new("set_log_filter")
+        }
+    }
+}
+
+/// The tray's answer to a [`Request`], also one line of JSON.
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    /// Auto-hide state after the request, when it was about the taskbar.
+    pub auto_hide: Option<bool>,
+    /// The change `undo` reverted; absent when there was nothing to undo.
+    pub undone: Option<Event>,
+    /// Answer to `info`.
+    pub tray: Option<TrayInfo>,
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn success(auto_hide: Option<bool>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            auto_hide,
+            undone: None,
+            tray: None,
+            error: None,
+        }
+    }
+
+    fn failure(error: impl std::fmt::Display) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            auto_hide: None,
+            undone: None,
+            tray: None,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// The auto-hide state, or the tray's error.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the tray's message when the request failed or the state is missing.
+    pub fn into_auto_hide(self) -> Result<bool> {
+        match (self.ok, self.auto_hide) {
+            (true, Some(auto_hide)) => Ok(auto_hide),
+            (true, None) => bail!("The running tray did not report the taskbar state"),
+            (false, _) => Err(eyre!(
+                "{}",
+                self.error
+                    .unwrap_or_else(|| "The running tray failed".to_string())
+            )),
+        }
+    }
+}
+
+/// Describes the running tray, for `tb tray-status`.
+#[derive(Facet, Debug, Clone, Default)]
+#[facet(default)]
+pub struct TrayInfo {
+    pub pid: u32,
+    pub version: String,
+    /// When the tray started, in Unix milliseconds.
+    pub started: u64,
+    /// The bindings the tray registered, as written in the config.
+    pub bindings: Vec<String>,
+}
+
+/// Per user, so trays of different users on one machine do not answer each other.
+fn endpoint_file_name() -> String {
+    let user = std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default();
+    format!("tb-{user}.sock")
+}
+
+/// The named pipe `\\.\pipe\tb-<user>.sock`.
+#[cfg(windows)]
+fn endpoint() -> Result<Name<'static>> {
+    endpoint_file_name()
+        .to_ns_name::<GenericNamespaced>()
+        .wrap_err("Invalid control endpoint name")
+}
+
+/// A socket file in [`socket_dir`], so only the user can connect to it.
+#[cfg(unix)]
+fn endpoint() -> Result<Name<'static>> {
+    socket_dir()?
+        .join(endpoint_file_name())
+        .to_fs_name::<GenericFilePath>()
+        .wrap_err("Invalid control endpoint name")
+}
+
+/// `tb` under `$XDG_RUNTIME_DIR`, or `run` under the cache directory where there is no runtime
+/// directory. [`serve`] creates it with access for the user alone.
+#[cfg(unix)]
+fn socket_dir() -> Result<PathBuf> {
+    let runtime_dir = directories_next::BaseDirs::new()
+        .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.join("tb")));
+    match runtime_dir {
+        Some(dir) => Ok(dir),
+        None => Ok(paths::cache_home()?.path().join("run")),
+    }
+}
+
+#[cfg(unix)]
+fn create_socket_dir() -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let dir = socket_dir()?;
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .and_then(|()| std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)))
+        .wrap_err_with(|| format!("Failed to create {}", dir.display()))
+}
+
+/// Answers requests on the control endpoint from a background thread for as long as the process
+/// runs. `on_change` is called with the new state after every request that changed it, and `tray`
+/// answers `info`.
+///
+/// Each connection gets its own thread, so a client that connects and never writes cannot hold
+/// up the others.
+///
+/// # Errors
+///
+/// Fails when the control endpoint cannot be opened or its thread cannot start.
+pub fn serve(on_change: fn(bool), tray: TrayInfo) -> Result<()> {
+    #[cfg(unix)]
+    create_socket_dir()?;
+    // A socket file left by a tray that crashed would otherwise block the name; the instance lock
+    // already keeps a second tray from getting this far.
+    let listener = ListenerOptions::new()
+        .name(endpoint()?)
+        .try_overwrite(true)
+        .create_sync()
+        .wrap_err("Failed to open the control endpoint")?;
+    std::thread::Builder::new()
+        .name("tb-ipc".to_string())
+        .spawn(move || {
+            for connection in listener.incoming() {
+                let connection = match connection {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        tracing::warn!("Failed to accept a control connection: {error}");
+                        continue;
+                    }
+                };
+                let tray = tray.clone();
+                let spawned = std::thread::Builder::new()
+                    .name("tb-ipc-connection".to_string())
+                    .spawn(move || {
+                        if let Err(error) = serve_connection(connection, on_change, &tray) {
+                            tracing::debug!("Control connection ended: {error}");
+                        }
+                    });
+                if let Err(error) = spawned {
+                    tracing::warn!("Failed to start a control connection thread: {error}");
+                }
+            }
+        })
+        .wrap_err("Failed to start the control endpoint thread")?;
+    Ok(())
+}
+
+fn serve_connection(connection: Stream, on_change: fn(bool), tray: &TrayInfo) -> io::Result<()> {
+    let mut connection = BufReader::new(connection);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if connection.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match facet_json::from_str::<Request>(line.trim()) {
+            Ok(request) => handle(&request, on_change, tray),
+            Err(error) => Response::failure(format!("Invalid request: {error}")),
+        };
+        let mut json = facet_json::to_string(&response);
+        json.push('\n');
+        connection.get_mut().write_all(json.as_bytes())?;
+    }
+}
+
+/// Carries out one request against the taskbar.
+pub fn handle(request: &Request, on_change: fn(bool), tray: &TrayInfo) -> Response {
+    if request.version != PROTOCOL_VERSION {
+        return Response::failure(format!(
+            "Unsupported protocol version {} (the tray speaks {PROTOCOL_VERSION})",
+            request.version
+        ));
+    }
+    let result = match request.method.as_str() {
+        "ping" => return Response::success(None),
+        "info" => {
+            return Response {
+                tray: Some(tray.clone()),
+                ..Response::success(None)
+            };
+        }
+        "set_log_filter" => {
+            let filter = request.filter.as_deref().unwrap_or_default();
+            return match loglevel::set_filter(filter) {
+                Ok(()) => Response::success(None),
+                Err(error) => Response::failure(format!("{error:#}")),
+            };
+        }
+        "undo" => {
+            return match history::undo() {
+                Ok(Some((change, auto_hide))) => {
+                    on_change(auto_hide);
+                    Response {
+                        undone: Some(change),
+                        ..Response::success(Some(auto_hide))
+                    }
+                }
+                Ok(None) => Response::success(None),
+                Err(error) => Response::failure(format!("{error:#}")),
+            };
+        }
+        "status" => taskbar::is_taskbar_auto_hide_enabled(),
+        method => Action::parse(method).and_then(|action| {
+            let source = request
+                .source
+                .as_deref()
+                .and_then(Source::parse)
+                .unwrap_or(Source::Cli);
+            let enabled = action.perform(source)?;
+            on_change(enabled);
+            Ok(enabled)
+        }),
+    };
+    match result {
+        Ok(auto_hide) => Response::success(Some(auto_hide)),
+        Err(error) => Response::failure(format!("{error:#}")),
+    }
+}
+
+/// Sends `request` to the running tray. Returns `None` when no tray is listening, so callers can
+/// fall back to acting on the taskbar themselves, and fails when the tray does not answer within
+/// [`RESPONSE_TIMEOUT`].
+///
+/// # Errors
+///
+/// Fails when the tray answers late, unreadably or in another protocol version.
+pub fn send(request: &Request) -> Result<Option<Response>> {
+    let endpoint = endpoint()?;
+    let mut json = facet_json::to_string(request);
+    json.push('\n');
+    // The exchange runs on its own thread because local sockets have no read timeout; a tray
+    // that never answers only leaves that thread behind.
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("tb-ipc-send".to_string())
+        .spawn(move || {
+            let _ = sender.send(exchange(endpoint, &json));
+        })
+        .wrap_err("Failed to start the control request thread")?;
+    let Some(line) = receiver.recv_timeout(RESPONSE_TIMEOUT).map_err(|_| {
+        eyre!(
+            "The running tray did not answer within {} seconds",
+            RESPONSE_TIMEOUT.as_secs()
+        )
+    })??
+    else {
+        return Ok(None);
+    };
+    let response: Response = facet_json::from_str(line.trim())
+        .map_err(|error| eyre!("Invalid response from the running tray: {error}"))?;
+    if response.version != PROTOCOL_VERSION {
+        bail!(
+            "The running tray speaks protocol version {} but this tb speaks {PROTOCOL_VERSION}; \
+             restart it with `tb --replace`",
+            response.version
+        )
+    }
+    Ok(Some(response))
+}
+
+/// Writes one request line and reads the response line, or `None` when no tray is listening.
+fn exchange(endpoint: Name<'static>, request: &str) -> Result<Option<String>> {
+    let connection = match Stream::connect(endpoint) {
+        Ok(connection) => connection,
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None);
+        }
+        Err(error) => return Err(error).wrap_err("Failed to reach the running tray"),
+    };
+    let mut connection = BufReader::new(connection);
+    connection
+        .get_mut()
+        .write_all(request.as_bytes())
+        .wrap_err("Failed to send the request to the running tray")?;
+    let mut line = String::new();
+    connection
+        .read_line(&mut line)
+        .wrap_err("Failed to read the running tray's response")?;
+    Ok(Some(line))
+}
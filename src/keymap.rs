@@ -0,0 +1,181 @@
+use crate::action::Action;
+use crate::condition::Condition;
+use crate::config::{self, BindingConfig};
+use crate::hotkey::{self, DEFAULT_HOTKEY_EXPRESSION, Hotkey};
+use crate::mouse::MouseTrigger;
+use crate::store::FileLock;
+use eyre::Result;
+
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Hotkey(Hotkey),
+    Mouse(MouseTrigger),
+}
+
+impl Trigger {
+    pub fn parse(expression: &str) -> Result<Self> {
+        if MouseTrigger::is_mouse_expression(expression) {
+            Ok(Trigger::Mouse(MouseTrigger::parse(expression)?))
+        } else {
+            Ok(Trigger::Hotkey(hotkey::parse_hotkey_expression(expression)?))
+        }
+    }
+
+    pub fn hotkey(&self) -> Option<&Hotkey> {
+        match self {
+            Trigger::Hotkey(hotkey) => Some(hotkey),
+            Trigger::Mouse(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Hotkey(hotkey) => write!(f, "{}", hotkey.expression),
+            Trigger::Mouse(trigger) => write!(f, "{trigger}"),
+        }
+    }
+}
+
+/// A hotkey or mouse trigger, the action it runs and an optional foreground-window condition.
+///
+/// Stored as `[[bindings]]` in `config.toml`. The single-line form
+/// `<trigger> = <action> [if|unless <field>:<pattern>]` is what `hotkey.txt` used before the
+/// config file existed; a bare hotkey there means `toggle`.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub trigger: Trigger,
+    pub action: Action,
+    pub condition: Option<Condition>,
+}
+
+impl Binding {
+    pub fn parse_line(line: &str) -> Result<Self> {
+        let Some((expression, rest)) = line.split_once('=') else {
+            return Ok(Self {
+                trigger: Trigger::parse(line)?,
+                action: Action::Toggle,
+                condition: None,
+            });
+        };
+
+        let rest = rest.trim();
+        let (action, condition) = match rest.split_once(char::is_whitespace) {
+            Some((action, condition)) => (action, Some(Condition::parse(condition)?)),
+            None => (rest, None),
+        };
+        Ok(Self {
+            trigger: Trigger::parse(expression)?,
+            action: Action::parse(action)?,
+            condition,
+        })
+    }
+
+    pub fn from_config(config: &BindingConfig) -> Result<Self> {
+        Ok(Self {
+            trigger: Trigger::parse(&config.trigger)?,
+            action: Action::parse(&config.action)?,
+            condition: config
+                .condition
+                .as_deref()
+                .map(Condition::parse)
+                .transpose()?,
+        })
+    }
+
+    pub fn to_config(&self) -> BindingConfig {
+        BindingConfig {
+            trigger: self.trigger.to_string(),
+            action: self.action.name().to_string(),
+            condition: self.condition.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.trigger, self.action.name())?;
+        if let Some(condition) = &self.condition {
+            write!(f, " {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn default_keymap() -> Result<Vec<Binding>> {
+    Ok(vec![Binding {
+        trigger: Trigger::Hotkey(hotkey::parse_hotkey_expression(DEFAULT_HOTKEY_EXPRESSION)?),
+        action: Action::Toggle,
+        condition: None,
+    }])
+}
+
+pub fn load_keymap() -> Result<Vec<Binding>> {
+    config::load_config()?.keymap()
+}
+
+/// The bindings in the user's `config.toml` alone, which is what keymap edits start from so the
+/// machine-wide layer is never copied into the user file.
+fn load_user_keymap() -> Result<Vec<Binding>> {
+    config::load_config_file()?.keymap()
+}
+
+pub fn save_keymap(bindings: &[Binding], lock: &FileLock) -> Result<()> {
+    let mut config = config::load_config_file()?;
+    config.bindings = bindings.iter().map(Binding::to_config).collect();
+    config::save_config(&config, lock)
+}
+
+/// Returns the first hotkey binding's hotkey, which is the one `tb hotkey show|set` manages.
+pub fn load_hotkey() -> Result<Hotkey> {
+    match load_keymap()?
+        .into_iter()
+        .find_map(|binding| binding.trigger.hotkey().cloned())
+    {
+        Some(hotkey) => Ok(hotkey),
+        None => hotkey::parse_hotkey_expression(DEFAULT_HOTKEY_EXPRESSION),
+    }
+}
+
+pub fn save_hotkey_expression(expression: &str) -> Result<Hotkey> {
+    let hotkey = hotkey::parse_hotkey_expression(expression)?;
+    let lock = config::lock_config()?;
+    let mut bindings = load_user_keymap()?;
+    match bindings
+        .iter_mut()
+        .find(|binding| binding.trigger.hotkey().is_some())
+    {
+        Some(binding) => binding.trigger = Trigger::Hotkey(hotkey.clone()),
+        None => bindings.insert(
+            0,
+            Binding {
+                trigger: Trigger::Hotkey(hotkey.clone()),
+                action: Action::Toggle,
+                condition: None,
+            },
+        ),
+    }
+    save_keymap(&bindings, &lock)?;
+    Ok(hotkey)
+}
+
+/// Adds bindings to the keymap, replacing any existing unconditional binding for the same trigger.
+pub fn merge_bindings(incoming: Vec<Binding>) -> Result<Vec<Binding>> {
+    let lock = config::lock_config()?;
+    let mut bindings = load_user_keymap()?;
+    for binding in incoming {
+        match bindings
+            .iter_mut()
+            .find(|existing| {
+                existing.condition.is_none()
+                    && existing.trigger.to_string() == binding.trigger.to_string()
+            })
+        {
+            Some(existing) => *existing = binding,
+            None => bindings.push(binding),
+        }
+    }
+    save_keymap(&bindings, &lock)?;
+    Ok(bindings)
+}
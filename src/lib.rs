@@ -1,13 +1,56 @@
 #![deny(clippy::disallowed_methods)]
 #![deny(clippy::disallowed_macros)]
 
+mod action;
+mod bundle;
+mod cache;
 mod cli;
+mod condition;
+mod config;
+mod dispatch;
+#[cfg(windows)]
+mod doctor;
+mod events;
+mod history;
+mod hooks;
 mod hotkey;
+/// Public so the integration tests can drive the REST API.
+#[cfg(feature = "http")]
+pub mod http;
+mod import;
+#[cfg(windows)]
+mod instance;
+/// Public so the integration tests can drive the control endpoint.
+pub mod ipc;
+mod keycode;
+mod keymap;
+mod logbuffer;
+mod logfile;
+mod loglevel;
+mod mouse;
 mod paths;
+mod serve;
+mod settings;
+mod store;
 mod taskbar;
+#[cfg(windows)]
 mod tray;
 
-use crate::cli::{Cli, Command, HotkeyCommand};
+use crate::cli::{
+    CacheCommand, Cli, Command, ConfigCommand, GlobalArgs, HotkeyCommand, LogsCommand,
+    SchemaCommand,
+};
+use crate::settings::CliOverride;
+use eyre::{Context, bail};
+use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Lines `tb logs tail`/`follow` print when `--lines` is not given.
+const DEFAULT_TAIL_LINES: usize = 50;
+/// Days `tb stats` lists when `--days` is not given.
+const DEFAULT_STATS_DAYS: usize = 7;
 const VERSION: &str = concat!(
     env!("CARGO_PKG_VERSION"),
     " (rev ",
@@ -28,19 +71,55 @@ pub fn main() -> eyre::Result<()> {
     .run()
     .unwrap();
 
-    init_tracing(cli.global.debug)?;
+    paths::set_portable(cli.global.portable);
+    settings::set_cli_overrides(cli_overrides(&cli.global)?);
+
+    let (config, config_error) = match config::load_config() {
+        Ok(config) => (Some(config), None),
+        Err(error) => (None, Some(error)),
+    };
+    // `tb serve --stdio` owns stdout for its protocol.
+    let console_to_stderr = matches!(cli.command, Some(Command::Serve(_)));
+    // `tb paths` reports what exists, so it must not create anything: no log file, no migration.
+    let read_only = matches!(cli.command, Some(Command::Paths(_)));
+    init_tracing(
+        &log_level(cli.global.debug, config.as_ref()),
+        config.as_ref(),
+        console_to_stderr,
+        !read_only,
+    )?;
+    if let Some(error) = config_error {
+        tracing::warn!("Using the default settings because the config failed to load: {error:#}");
+    }
+    if !read_only && let Some(legacy) = config::migrate_legacy_hotkeys()? {
+        tracing::info!(
+            "Migrated {} to {}",
+            legacy.display(),
+            config::config_path()?.display()
+        );
+    }
+    if let Some(config) = &config {
+        hooks::configure(config.hooks.clone());
+    }
+    // Declared before anything can run a hook, so it is dropped after every return below.
+    let _hooks = hooks::WaitOnDrop;
 
     let command = cli.command.unwrap_or(Command::Run);
 
     match command {
-        Command::Run => tray::run_tray(VERSION),
+        #[cfg(windows)]
+        Command::Run => tray::run_tray(VERSION, cli.global.replace),
+        #[cfg(not(windows))]
+        Command::Run | Command::Stop | Command::Restart | Command::Doctor(_) => {
+            bail!("This command requires Windows")
+        }
         Command::Toggle => {
-            let enabled = taskbar::toggle_taskbar_auto_hide()?;
+            let enabled = dispatch::perform(action::Action::Toggle, action::Source::Cli)?;
             println!("taskbar auto-hide: {}", if enabled { "enabled" } else { "disabled" });
             Ok(())
         }
         Command::Status => {
-            let enabled = taskbar::is_taskbar_auto_hide_enabled()?;
+            let enabled = dispatch::state()?;
             println!("taskbar auto-hide: {}", if enabled { "enabled" } else { "disabled" });
             Ok(())
         }
@@ -48,42 +127,514 @@ pub fn main() -> eyre::Result<()> {
             let home = paths::app_home()?;
             home.ensure_dir()?;
             println!("{}", home.path().display());
+            report_portable();
             Ok(())
         }
-        Command::Cache => {
-            let cache = paths::cache_home()?;
-            cache.ensure_dir()?;
-            println!("{}", cache.path().display());
-            Ok(())
-        }
+        Command::Cache(args) => match args.command {
+            CacheCommand::Path => {
+                let cache = paths::cache_home()?;
+                cache.ensure_dir()?;
+                println!("{}", cache.path().display());
+                report_portable();
+                Ok(())
+            }
+            CacheCommand::Size => {
+                let size = cache::cache_size()?;
+                println!("{} ({size} bytes)", paths::format_size(size));
+                Ok(())
+            }
+            CacheCommand::Clean { older_than, all } => {
+                let older_than = older_than.as_deref().map(cache::parse_age).transpose()?;
+                let report = cache::clean(older_than, all)?;
+                println!(
+                    "removed {} file(s), {}",
+                    report.files,
+                    paths::format_size(report.bytes)
+                );
+                if report.failed > 0 {
+                    println!("{} file(s) are in use and were kept", report.failed);
+                }
+                Ok(())
+            }
+        },
         Command::Hotkey(args) => match args.command {
             HotkeyCommand::Set { expression } => {
-                let hotkey = hotkey::save_hotkey_expression(&expression)?;
+                let hotkey = keymap::save_hotkey_expression(&expression)?;
                 println!("{}", hotkey.expression);
                 Ok(())
             }
             HotkeyCommand::Show => {
-                let hotkey = hotkey::load_hotkey()?;
+                let hotkey = keymap::load_hotkey()?;
                 println!("{}", hotkey.expression);
                 Ok(())
             }
+            HotkeyCommand::Import { from, file } => {
+                let source = import::ImportSource::parse(&from)?;
+                let report = import::import_file(source, std::path::Path::new(&file))?;
+                for binding in &report.bindings {
+                    println!("imported: {binding}");
+                }
+                for skipped in &report.skipped {
+                    println!("skipped: {} ({})", skipped.entry, skipped.reason);
+                }
+                if !report.bindings.is_empty() {
+                    keymap::merge_bindings(report.bindings)?;
+                }
+                Ok(())
+            }
+        },
+        Command::Config(args) => run_config_command(args.command),
+        Command::Paths(args) => {
+            if args.create {
+                for known in paths::known_paths()?.iter().filter(|known| known.is_dir) {
+                    std::fs::create_dir_all(&known.path)
+                        .wrap_err_with(|| format!("Failed to create {}", known.path))?;
+                }
+            }
+            let known = paths::known_paths()?;
+            if args.json {
+                println!("{}", facet_json::to_string(&known));
+                return Ok(());
+            }
+            let width = known.iter().map(|known| known.name.len()).max().unwrap_or(0);
+            for known in &known {
+                let state = match known.size {
+                    Some(size) => paths::format_size(size),
+                    None => "missing".to_string(),
+                };
+                println!(
+                    "{:<width$}  {}  [{state}] ({})",
+                    known.name, known.path, known.source
+                );
+            }
+            report_portable();
+            Ok(())
+        }
+        Command::Logs(args) => match args.command {
+            LogsCommand::Path => {
+                println!("{}", logfile::log_path()?.display());
+                Ok(())
+            }
+            LogsCommand::Tail { lines } => {
+                for line in logfile::tail(lines.unwrap_or(DEFAULT_TAIL_LINES))? {
+                    println!("{line}");
+                }
+                Ok(())
+            }
+            LogsCommand::Follow { lines } => logfile::follow(lines.unwrap_or(DEFAULT_TAIL_LINES)),
+            LogsCommand::Level { filter } => {
+                loglevel::parse(&filter)?;
+                match ipc::send(&ipc::Request::set_log_filter(&filter))? {
+                    Some(response) if response.ok => {}
+                    Some(response) => bail!(
+                        "{}",
+                        response
+                            .error
+                            .unwrap_or_else(|| "The running tray failed".to_string())
+                    ),
+                    None => bail!("No running tb tray was found"),
+                }
+                println!("log filter of the running tray set to {}", filter.trim());
+                Ok(())
+            }
+            LogsCommand::Clear => {
+                let removed = logfile::clear()?;
+                println!("cleared the log and removed {removed} rotated file(s)");
+                Ok(())
+            }
         },
+        #[cfg(windows)]
+        Command::Doctor(args) => {
+            let checks = doctor::run_checks();
+            for check in &checks {
+                println!("{check}");
+            }
+            if args.report {
+                let report = doctor::write_report(&checks, VERSION)?;
+                println!("report written to {}", report.display());
+            }
+            let failed = checks
+                .iter()
+                .filter(|check| check.status == doctor::Status::Fail)
+                .count();
+            if failed > 0 {
+                bail!("{failed} check(s) failed")
+            }
+            Ok(())
+        }
+        Command::Stats(args) => {
+            let events = events::load()?;
+            let stats = events::summarize(&events, events::now_millis());
+            let Some(since) = stats.since else {
+                println!("no taskbar changes recorded yet");
+                return Ok(());
+            };
+            println!("since {} (UTC)", events::format_date(since));
+            let total = (stats.hidden + stats.shown).as_secs_f64().max(1.0);
+            println!(
+                "hidden {} ({:.0}%), shown {} ({:.0}%)",
+                events::format_duration(stats.hidden),
+                stats.hidden.as_secs_f64() / total * 100.0,
+                events::format_duration(stats.shown),
+                stats.shown.as_secs_f64() / total * 100.0
+            );
+            if let Some((source, count)) = stats.triggers.first() {
+                println!(
+                    "most-used trigger: {source} ({count} of {} action(s))",
+                    events.len()
+                );
+            }
+            let days = args.days.unwrap_or(DEFAULT_STATS_DAYS);
+            println!("toggles per day:");
+            for (date, count) in &stats.per_day[stats.per_day.len().saturating_sub(days)..] {
+                println!("  {date}  {count}");
+            }
+            Ok(())
+        }
+        Command::History => {
+            let history = history::list()?;
+            if history.is_empty() {
+                println!("no taskbar changes recorded yet");
+            }
+            for change in &history {
+                println!("{change}");
+            }
+            Ok(())
+        }
+        Command::Undo => match dispatch::undo()? {
+            Some((change, enabled)) => {
+                println!("undid {change}");
+                println!("taskbar auto-hide: {}", if enabled { "enabled" } else { "disabled" });
+                Ok(())
+            }
+            None => bail!("Nothing to undo"),
+        },
+        #[cfg(windows)]
+        Command::Stop => {
+            if tray::stop_running_tray()? {
+                println!("stopped the running tray");
+            } else {
+                println!("no tray is running");
+            }
+            Ok(())
+        }
+        #[cfg(windows)]
+        Command::Restart => {
+            if tray::stop_running_tray()? {
+                println!("stopped the running tray");
+            }
+            let pid = tray::spawn_tray()?;
+            println!("started a new tray (pid {pid})");
+            Ok(())
+        }
+        Command::TrayStatus => {
+            let Some(response) = ipc::send(&ipc::Request::new("info"))? else {
+                println!("no tray is running");
+                return Ok(());
+            };
+            let Some(info) = response.tray else {
+                bail!(
+                    "{}",
+                    response
+                        .error
+                        .unwrap_or_else(|| "The running tray did not describe itself".to_string())
+                )
+            };
+            let uptime = events::now_millis().saturating_sub(info.started);
+            println!("pid: {}", info.pid);
+            println!("version: {}", info.version);
+            println!(
+                "uptime: {}",
+                events::format_duration(std::time::Duration::from_millis(uptime))
+            );
+            println!("bindings:");
+            for binding in &info.bindings {
+                println!("  {binding}");
+            }
+            Ok(())
+        }
+        Command::Serve(args) => match (args.stdio, args.http) {
+            (true, true) => bail!("--stdio and --http cannot be combined"),
+            (true, false) => serve::serve_stdio(),
+            (false, true) => serve_http(args.bind.as_deref()),
+            (false, false) => bail!("tb serve needs a transport; pass --stdio or --http"),
+        },
+        Command::Schema(args) => {
+            let schema = match args.command {
+                SchemaCommand::Config => facet_json_schema::to_string::<config::Config>(),
+                SchemaCommand::Cli => facet_json_schema::to_string::<Cli>(),
+            };
+            println!("{schema}");
+            Ok(())
+        }
+    }
+}
+
+fn run_config_command(command: ConfigCommand) -> eyre::Result<()> {
+    match command {
+        ConfigCommand::List => {
+            for entry in settings::resolve()?.entries {
+                print_setting(&entry);
+            }
+            Ok(())
+        }
+        ConfigCommand::Get { key } => {
+            settings::location(&key)?;
+            let resolution = settings::resolve()?;
+            match resolution.entries.iter().find(|entry| entry.key == key) {
+                Some(entry) => print_setting(entry),
+                None => bail!("Setting '{key}' does not exist in the current configuration"),
+            }
+            Ok(())
+        }
+        ConfigCommand::Set { key, value } => {
+            let value = config::set_config_value(&key, &value)?;
+            println!("{key} = {value}");
+            Ok(())
+        }
+        ConfigCommand::Unset { key } => {
+            config::unset_config_value(&key)?;
+            let resolution = settings::resolve()?;
+            if let Some(entry) = resolution.entries.iter().find(|entry| entry.key == key) {
+                print_setting(entry);
+            }
+            Ok(())
+        }
+        ConfigCommand::Explain { key } => {
+            settings::location(&key)?;
+            let resolution = settings::resolve()?;
+            let entry = resolution
+                .entries
+                .iter()
+                .find(|entry| entry.key == key)
+                .ok_or_else(|| {
+                    eyre::eyre!("Setting '{key}' does not exist in the current configuration")
+                })?;
+            println!("{key}");
+            for layer in &entry.chain {
+                let value = match (layer.present, &layer.value) {
+                    (false, _) => "(not set)".to_string(),
+                    (true, Some(value)) => value.to_toml(),
+                    (true, None) => "<unset>".to_string(),
+                };
+                println!("  {:<48} {value}", layer.source.to_string());
+            }
+            let value = entry
+                .value
+                .as_ref()
+                .map_or_else(|| "<unset>".to_string(), settings::Value::to_toml);
+            println!("effective: {value} (from {})", entry.source);
+            Ok(())
+        }
+        ConfigCommand::Export { file } => {
+            let bundle = bundle::export_bundle(std::path::Path::new(&file), VERSION)?;
+            println!(
+                "exported config and {} snapshot(s) to {file}",
+                bundle.snapshots.len()
+            );
+            Ok(())
+        }
+        ConfigCommand::Import {
+            file,
+            merge,
+            replace,
+            dry_run,
+        } => {
+            let mode = match (merge, replace) {
+                (true, true) => bail!("--merge and --replace cannot be combined"),
+                (_, true) => bundle::ImportMode::Replace,
+                _ => bundle::ImportMode::Merge,
+            };
+            import_config(&file, mode, dry_run)
+        }
+        ConfigCommand::Edit => {
+            let path = config::config_path()?;
+            if !path.exists() {
+                config::save_config(&config::Config::default(), &config::lock_config()?)?;
+            }
+            open_in_editor(&path)?;
+            config::read_config_file()?;
+            println!("{} is valid", path.display());
+            Ok(())
+        }
+        ConfigCommand::Validate => {
+            let path = config::config_path()?;
+            config::read_config_file()?;
+            settings::resolve()?;
+            println!("{} is valid", path.display());
+            Ok(())
+        }
+        ConfigCommand::Path => {
+            println!("{}", config::config_path()?.display());
+            Ok(())
+        }
     }
 }
 
-fn init_tracing(debug: bool) -> eyre::Result<()> {
-    let level = if debug {
-        tracing::level_filters::LevelFilter::DEBUG
+/// Prints what importing the bundle at `file` changes, then applies it unless `dry_run` is set.
+fn import_config(file: &str, mode: bundle::ImportMode, dry_run: bool) -> eyre::Result<()> {
+    let bundle = bundle::read_bundle(std::path::Path::new(file))?;
+    let plan = bundle::plan_import(bundle, mode)?;
+    for change in &plan.changes {
+        match (&change.before, &change.after) {
+            (None, Some(after)) => println!("+ {} = {}", change.key, after.to_toml()),
+            (Some(before), None) => println!("- {} = {}", change.key, before.to_toml()),
+            (Some(before), Some(after)) => println!(
+                "~ {} = {} -> {}",
+                change.key,
+                before.to_toml(),
+                after.to_toml()
+            ),
+            (None, None) => {}
+        }
+    }
+    for name in &plan.new_snapshots {
+        println!("+ snapshot {name}");
+    }
+    if plan.changes.is_empty() && plan.new_snapshots.is_empty() {
+        println!("nothing to import");
+    } else if dry_run {
+        println!("dry run: nothing was written");
     } else {
-        tracing::level_filters::LevelFilter::INFO
+        bundle::apply_import(&plan)?;
+    }
+    Ok(())
+}
+
+/// Collects `--set key=value` flags, then the dedicated flags, so e.g. `--debug` wins over
+/// `--set logging.level`.
+fn cli_overrides(global: &GlobalArgs) -> eyre::Result<Vec<CliOverride>> {
+    let mut overrides = Vec::new();
+    for assignment in &global.set {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| eyre::eyre!("--set expects key=value, found '{assignment}'"))?;
+        let key = key.trim();
+        settings::location(key).wrap_err("Invalid --set")?;
+        overrides.push(CliOverride {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+            flag: format!("--set {key}"),
+        });
+    }
+    if let Some(format) = &global.log_format {
+        overrides.push(CliOverride {
+            key: "logging.format".to_string(),
+            value: format.clone(),
+            flag: "--log-format".to_string(),
+        });
+    }
+    if global.debug {
+        overrides.push(CliOverride {
+            key: "logging.level".to_string(),
+            value: "debug".to_string(),
+            flag: "--debug".to_string(),
+        });
+    }
+    Ok(overrides)
+}
+
+#[cfg(feature = "http")]
+fn serve_http(bind: Option<&str>) -> eyre::Result<()> {
+    http::serve(bind.unwrap_or(http::DEFAULT_BIND))
+}
+
+#[cfg(not(feature = "http"))]
+fn serve_http(_bind: Option<&str>) -> eyre::Result<()> {
+    bail!("This tb was built without the `http` feature; rebuild with `--features http`")
+}
+
+/// Notes portable mode on stderr so `tb home`/`tb cache` output stays usable in scripts.
+fn report_portable() {
+    if let Some(root) = paths::portable_root() {
+        eprintln!("portable mode ({})", root.display());
+    }
+}
+
+fn print_setting(entry: &settings::Resolved) {
+    let value = entry
+        .value
+        .as_ref()
+        .map_or_else(|| "<unset>".to_string(), ToString::to_string);
+    println!("{} = {value} ({})", entry.key, entry.source);
+}
+
+/// Opens `path` in `$VISUAL`/`$EDITOR` (which may include arguments), falling back to Notepad.
+fn open_in_editor(path: &std::path::Path) -> eyre::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "notepad".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("notepad");
+
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .wrap_err_with(|| format!("Failed to launch editor '{editor}'"))?;
+    if !status.success() {
+        bail!("Editor '{editor}' exited with {status}")
+    }
+    Ok(())
+}
+
+fn log_level(debug: bool, config: Option<&config::Config>) -> String {
+    if debug {
+        return "debug".to_string();
+    }
+    config.map_or_else(|| "info".to_string(), |config| config.logging.level.clone())
+}
+
+/// Logs to the console (stdout, or stderr when `console_to_stderr`), to an in-memory buffer
+/// replayed by "Show logs" and, unless it cannot be opened, to the rotating log file, all in the
+/// `logging.format` format.
+fn init_tracing(
+    level: &str,
+    config: Option<&config::Config>,
+    console_to_stderr: bool,
+    file_log: bool,
+) -> eyre::Result<()> {
+    let logging = config.map(|config| config.logging.clone()).unwrap_or_default();
+    let format = logging.format.as_str();
+
+    let console = if console_to_stderr {
+        format_layer(format, true, std::io::stderr)
+    } else {
+        format_layer(format, true, std::io::stdout)
     };
+    let mut layers = vec![
+        console,
+        format_layer(format, true, || logbuffer::RingBufferWriter),
+    ];
+    if file_log {
+        match logfile::RollingFileWriter::open(&logging) {
+            Ok(writer) => layers.push(format_layer(format, false, move || writer.clone())),
+            Err(error) => eprintln!("Logging to the console only: {error:#}"),
+        }
+    }
 
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_ansi(true)
-        .with_max_level(level)
+    tracing_subscriber::registry()
+        .with(loglevel::reloadable(level)?)
+        .with(layers)
         .try_init()
         .map_err(|error| eyre::eyre!("Failed to initialize logging: {error}"))?;
 
     Ok(())
 }
+
+/// A formatting layer for one log destination. JSON output includes the fields of the current
+/// span, such as those `Action::perform` records.
+fn format_layer<S, W>(format: &str, ansi: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_writer(writer);
+    match format {
+        "json" => layer.json().with_current_span(true).with_span_list(false).boxed(),
+        "compact" => layer.compact().with_ansi(ansi).boxed(),
+        _ => layer.with_ansi(ansi).boxed(),
+    }
+}
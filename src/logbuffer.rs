@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Mutex, PoisonError};
+
+/// How many formatted events are kept for replay.
+pub const CAPACITY: usize = 1000;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Keeps the most recent [`CAPACITY`] formatted log events in memory, so a console opened later
+/// with "Show logs" can display what happened before it existed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RingBufferWriter;
+
+impl Write for RingBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+        recent.push_back(String::from_utf8_lossy(buf).into_owned());
+        while recent.len() > CAPACITY {
+            recent.pop_front();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every buffered event to stdout, oldest first.
+#[cfg(windows)]
+pub fn replay() -> io::Result<()> {
+    let recent = RECENT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut stdout = io::stdout().lock();
+    for event in recent {
+        stdout.write_all(event.as_bytes())?;
+    }
+    stdout.flush()
+}
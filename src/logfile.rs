@@ -0,0 +1,292 @@
+use crate::config::LoggingConfig;
+use crate::paths;
+use crate::store;
+use eyre::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The active log file; rotated files are named `tb-<unix millis>.log` beside it.
+pub const LOG_FILE: &str = "tb.log";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const FOLLOW_POLL: Duration = Duration::from_millis(250);
+/// How often a writer checks whether another process rotated or cleared the active file.
+const REPLACED_CHECK: Duration = Duration::from_secs(1);
+/// How much of the log `tail` reads at a time, walking back from the end.
+const TAIL_CHUNK: u64 = 8 * 1024;
+
+/// Appends log output to [`LOG_FILE`] under the cache's log directory.
+///
+/// The file is rotated when the day changes or it would grow past `logging.max_file_size_mb`,
+/// keeping `logging.max_files` rotated files. Several tb processes may share the file: each
+/// notices within a second when another one has rotated or cleared it and reopens.
+#[derive(Clone, Debug)]
+pub struct RollingFileWriter {
+    inner: Arc<Mutex<RollingFile>>,
+}
+
+#[derive(Debug)]
+struct RollingFile {
+    dir: PathBuf,
+    file: File,
+    day: u64,
+    /// Length of the active file, counting what this process wrote since it was last checked.
+    size: u64,
+    checked: Instant,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RollingFileWriter {
+    pub fn open(config: &LoggingConfig) -> Result<Self> {
+        let dir = paths::log_dir()?;
+        std::fs::create_dir_all(&dir)
+            .wrap_err_with(|| format!("Failed to create log directory {}", dir.display()))?;
+        let (file, day, size) = open_active(&dir)
+            .wrap_err_with(|| format!("Failed to open {}", dir.join(LOG_FILE).display()))?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RollingFile {
+                dir,
+                file,
+                day,
+                size,
+                checked: Instant::now(),
+                max_size: u64::from(config.max_file_size_mb) * 1024 * 1024,
+                max_files: usize::try_from(config.max_files).unwrap_or(usize::MAX),
+            })),
+        })
+    }
+}
+
+impl Write for RollingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.prepare(buf.len() as u64)?;
+        let written = inner.file.write(buf)?;
+        inner.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .file
+            .flush()
+    }
+}
+
+impl RollingFile {
+    /// Reopens the active file if another process replaced it, then rotates if `incoming` bytes
+    /// would overflow it or the day has changed.
+    ///
+    /// The file system is only consulted every [`REPLACED_CHECK`]; in between the tracked size
+    /// decides, so an ordinary write costs no extra system calls.
+    fn prepare(&mut self, incoming: u64) -> io::Result<()> {
+        let path = self.dir.join(LOG_FILE);
+        if self.checked.elapsed() >= REPLACED_CHECK {
+            self.checked = Instant::now();
+            let on_disk = std::fs::metadata(&path).map(|metadata| metadata.len()).ok();
+            let ours = self.file.metadata()?.len();
+            if on_disk == Some(ours) {
+                // Picks up what other processes appended.
+                self.size = ours;
+            } else {
+                (self.file, self.day, self.size) = open_active(&self.dir)?;
+            }
+        }
+
+        let too_big = self.max_size > 0 && self.size > 0 && self.size + incoming > self.max_size;
+        if !too_big && self.day == today() {
+            return Ok(());
+        }
+
+        let rotated = self.dir.join(format!("tb-{}.log", unix_millis()));
+        match std::fs::rename(&path, &rotated) {
+            Ok(()) => {}
+            // Another process rotated it first.
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        (self.file, self.day, self.size) = open_active(&self.dir)?;
+        store::prune(&path, &self.dir, self.max_files).map_err(io::Error::other)
+    }
+}
+
+/// Opens the active file for appending and returns it with the day it was last written and its
+/// length.
+fn open_active(dir: &Path) -> io::Result<(File, u64, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE))?;
+    let metadata = file.metadata()?;
+    let day = metadata.modified().map_or_else(|_| today(), day_of);
+    Ok((file, day, metadata.len()))
+}
+
+fn day_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECONDS_PER_DAY
+}
+
+fn today() -> u64 {
+    day_of(SystemTime::now())
+}
+
+fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+pub fn log_path() -> Result<PathBuf> {
+    Ok(paths::log_dir()?.join(LOG_FILE))
+}
+
+/// The last `count` lines of the active log file.
+pub fn tail(count: usize) -> Result<Vec<String>> {
+    let path = log_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    File::open(&path)
+        .and_then(|mut file| last_lines(&mut file, count))
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))
+}
+
+/// Reads `file` backwards in [`TAIL_CHUNK`] blocks until it has `count` complete lines, so the
+/// cost depends on `count` rather than on how large the log has grown.
+fn last_lines(file: &mut File, count: usize) -> io::Result<Vec<String>> {
+    let mut start = file.metadata()?.len();
+    let mut buffer = Vec::new();
+    let mut newlines = 0;
+    // One newline more than `count`: the last line ends with one, and the first line read is
+    // usually only the end of a longer one.
+    while start > 0 && newlines <= count {
+        let chunk = TAIL_CHUNK.min(start);
+        start -= chunk;
+        file.seek(SeekFrom::Start(start))?;
+        let mut block = vec![0; usize::try_from(chunk).map_err(io::Error::other)?];
+        file.read_exact(&mut block)?;
+        #[expect(clippy::naive_bytecount, reason = "one small chunk at a time")]
+        let found = block.iter().filter(|&&byte| byte == b'\n').count();
+        newlines += found;
+        block.extend_from_slice(&buffer);
+        buffer = block;
+    }
+
+    let text = String::from_utf8_lossy(&buffer);
+    let lines = text.lines().collect::<Vec<_>>();
+    Ok(lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// Prints the last `count` lines, then everything appended to the log until interrupted.
+pub fn follow(count: usize) -> Result<()> {
+    for line in tail(count)? {
+        println!("{line}");
+    }
+
+    let path = log_path()?;
+    let mut position = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    let mut stdout = io::stdout();
+    loop {
+        std::thread::sleep(FOLLOW_POLL);
+        let Ok(mut file) = File::open(&path) else {
+            position = 0;
+            continue;
+        };
+        let length = file.metadata()?.len();
+        if length < position {
+            // Rotated or cleared; start over with the new file.
+            position = 0;
+        }
+        if length == position {
+            continue;
+        }
+        file.seek(SeekFrom::Start(position))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        position += appended.len() as u64;
+        stdout.write_all(&appended)?;
+        stdout.flush()?;
+    }
+}
+
+/// Deletes rotated log files and empties the active one. Returns how many files were deleted.
+pub fn clear() -> Result<usize> {
+    let path = log_path()?;
+    let dir = paths::log_dir()?;
+    let rotated = store::snapshots(&path, &dir)?;
+    for file in &rotated {
+        std::fs::remove_file(file)
+            .wrap_err_with(|| format!("Failed to remove {}", file.display()))?;
+    }
+    if path.exists() {
+        OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Failed to clear {}", path.display()))?;
+    }
+    Ok(rotated.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write as _;
+
+    fn log_with_lines(name: &str, lines: usize) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("tb-{name}-{}.log", std::process::id()));
+        let mut contents = String::new();
+        for line in 0..lines {
+            let _ = writeln!(contents, "line {line} {}", "x".repeat(line % 50));
+        }
+        std::fs::write(&path, contents).unwrap();
+        let file = File::open(&path).unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn last_lines_spanning_several_chunks() {
+        let (path, mut file) = log_with_lines("tail-long", 5000);
+        for count in [0, 1, 10, 300, 4999, 5000, 6000] {
+            let lines = last_lines(&mut file, count).unwrap();
+            assert_eq!(lines.len(), count.min(5000), "count {count}");
+            if let Some(last) = lines.last() {
+                assert!(last.starts_with("line 4999 "), "{last}");
+            }
+            if let Some(first) = lines.first() {
+                let expected = 5000 - count.min(5000);
+                assert!(first.starts_with(&format!("line {expected} ")), "{first}");
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn last_lines_of_short_and_empty_files() {
+        let (path, mut file) = log_with_lines("tail-short", 3);
+        assert_eq!(last_lines(&mut file, 2).unwrap(), ["line 1 x", "line 2 xx"]);
+        std::fs::remove_file(path).unwrap();
+
+        let (path, mut file) = log_with_lines("tail-empty", 0);
+        assert!(last_lines(&mut file, 10).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn last_lines_without_a_trailing_newline() {
+        let path = std::env::temp_dir().join(format!("tb-tail-open-{}.log", std::process::id()));
+        std::fs::write(&path, "first\nsecond\nthird").unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(last_lines(&mut file, 2).unwrap(), ["second", "third"]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
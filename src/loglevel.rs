@@ -0,0 +1,42 @@
+use eyre::{Result, eyre};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static DIRECTIVES: Mutex<String> = Mutex::new(String::new());
+
+/// Parses a level (`debug`) or comma-separated `EnvFilter` directives (`info,tb::tray=trace`).
+pub fn parse(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(directives.trim())
+        .map_err(|error| eyre!("Invalid log filter '{directives}': {error}"))
+}
+
+/// Builds the filter layer installed by `init_tracing`, which [`set_filter`] can later replace.
+pub fn reloadable(directives: &str) -> Result<reload::Layer<EnvFilter, Registry>> {
+    let (layer, handle) = reload::Layer::new(parse(directives)?);
+    let _ = FILTER.set(handle);
+    *DIRECTIVES.lock().unwrap_or_else(PoisonError::into_inner) = directives.trim().to_string();
+    Ok(layer)
+}
+
+/// Swaps the active log filter without restarting.
+pub fn set_filter(directives: &str) -> Result<()> {
+    let filter = parse(directives)?;
+    FILTER
+        .get()
+        .ok_or_else(|| eyre!("Logging has not been initialized"))?
+        .reload(filter)
+        .map_err(|error| eyre!("Failed to change the log filter: {error}"))?;
+    *DIRECTIVES.lock().unwrap_or_else(PoisonError::into_inner) = directives.trim().to_string();
+    tracing::info!("Log filter set to {}", directives.trim());
+    Ok(())
+}
+
+/// The directives currently in effect.
+#[cfg(windows)]
+pub fn current() -> String {
+    DIRECTIVES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
@@ -0,0 +1,333 @@
+use eyre::{Result, bail};
+use std::fmt;
+use std::time::Duration;
+#[cfg(any(windows, test))]
+use std::time::Instant;
+
+/// How close to a screen corner or edge, in pixels, the cursor must be to count as inside it.
+#[cfg(any(windows, test))]
+pub const ZONE_THICKNESS: i32 = 2;
+pub const DEFAULT_DWELL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenZone {
+    Corner(Corner),
+    Edge(Edge),
+}
+
+/// Mouse gestures that can be bound to an action in the keymap.
+///
+/// Written as `tray:click`, `tray:double-click`, `tray:middle-click`, `tray:wheel-up`,
+/// `tray:wheel-down`, `corner:<top-left|top-right|bottom-left|bottom-right>[@<ms>ms]` or
+/// `edge:<top|bottom|left|right>[@<ms>ms]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseTrigger {
+    TrayClick,
+    TrayDoubleClick,
+    TrayMiddleClick,
+    TrayWheelUp,
+    TrayWheelDown,
+    Zone { zone: ScreenZone, dwell: Duration },
+}
+
+impl MouseTrigger {
+    pub fn is_mouse_expression(expression: &str) -> bool {
+        let lower = expression.trim().to_ascii_lowercase();
+        ["tray:", "corner:", "edge:"]
+            .iter()
+            .any(|prefix| lower.starts_with(prefix))
+    }
+
+    pub fn parse(expression: &str) -> Result<Self> {
+        let lower = expression.trim().to_ascii_lowercase();
+        let Some((kind, rest)) = lower.split_once(':') else {
+            bail!("Mouse trigger must look like 'tray:<gesture>', 'corner:<name>' or 'edge:<name>'")
+        };
+
+        if kind == "tray" {
+            return match rest {
+                "click" | "left-click" => Ok(MouseTrigger::TrayClick),
+                "double-click" | "dblclick" => Ok(MouseTrigger::TrayDoubleClick),
+                "middle-click" | "middle" => Ok(MouseTrigger::TrayMiddleClick),
+                "wheel-up" | "scroll-up" => Ok(MouseTrigger::TrayWheelUp),
+                "wheel-down" | "scroll-down" => Ok(MouseTrigger::TrayWheelDown),
+                other => bail!("Unsupported tray mouse gesture: {other}"),
+            };
+        }
+
+        let (name, dwell) = match rest.split_once('@') {
+            Some((name, dwell)) => (name, parse_dwell(dwell)?),
+            None => (rest, DEFAULT_DWELL),
+        };
+        let zone = match (kind, name) {
+            ("corner", "top-left") => ScreenZone::Corner(Corner::TopLeft),
+            ("corner", "top-right") => ScreenZone::Corner(Corner::TopRight),
+            ("corner", "bottom-left") => ScreenZone::Corner(Corner::BottomLeft),
+            ("corner", "bottom-right") => ScreenZone::Corner(Corner::BottomRight),
+            ("edge", "top") => ScreenZone::Edge(Edge::Top),
+            ("edge", "bottom") => ScreenZone::Edge(Edge::Bottom),
+            ("edge", "left") => ScreenZone::Edge(Edge::Left),
+            ("edge", "right") => ScreenZone::Edge(Edge::Right),
+            _ => bail!("Unsupported mouse trigger: {kind}:{name}"),
+        };
+        Ok(MouseTrigger::Zone { zone, dwell })
+    }
+}
+
+impl fmt::Display for MouseTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MouseTrigger::TrayClick => write!(f, "tray:click"),
+            MouseTrigger::TrayDoubleClick => write!(f, "tray:double-click"),
+            MouseTrigger::TrayMiddleClick => write!(f, "tray:middle-click"),
+            MouseTrigger::TrayWheelUp => write!(f, "tray:wheel-up"),
+            MouseTrigger::TrayWheelDown => write!(f, "tray:wheel-down"),
+            MouseTrigger::Zone { zone, dwell } => {
+                let name = match zone {
+                    ScreenZone::Corner(Corner::TopLeft) => "corner:top-left",
+                    ScreenZone::Corner(Corner::TopRight) => "corner:top-right",
+                    ScreenZone::Corner(Corner::BottomLeft) => "corner:bottom-left",
+                    ScreenZone::Corner(Corner::BottomRight) => "corner:bottom-right",
+                    ScreenZone::Edge(Edge::Top) => "edge:top",
+                    ScreenZone::Edge(Edge::Bottom) => "edge:bottom",
+                    ScreenZone::Edge(Edge::Left) => "edge:left",
+                    ScreenZone::Edge(Edge::Right) => "edge:right",
+                };
+                write!(f, "{name}@{}ms", dwell.as_millis())
+            }
+        }
+    }
+}
+
+fn parse_dwell(value: &str) -> Result<Duration> {
+    let millis = value.trim().trim_end_matches("ms");
+    match millis.parse::<u64>() {
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(_) => bail!("Invalid dwell time '{value}', expected milliseconds such as '300ms'"),
+    }
+}
+
+/// Screen rectangle in virtual-screen coordinates; `right` and `bottom` are exclusive.
+#[cfg(any(windows, test))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+/// Returns the corner or edge of `screen` that `(x, y)` is in. Corners win over edges.
+#[cfg(any(windows, test))]
+pub fn zone_at(x: i32, y: i32, screen: Rect) -> Option<ScreenZone> {
+    if x < screen.left || x >= screen.right || y < screen.top || y >= screen.bottom {
+        return None;
+    }
+
+    let near_left = x < screen.left + ZONE_THICKNESS;
+    let near_right = x >= screen.right - ZONE_THICKNESS;
+    let near_top = y < screen.top + ZONE_THICKNESS;
+    let near_bottom = y >= screen.bottom - ZONE_THICKNESS;
+
+    match (near_left, near_right, near_top, near_bottom) {
+        (true, _, true, _) => Some(ScreenZone::Corner(Corner::TopLeft)),
+        (_, true, true, _) => Some(ScreenZone::Corner(Corner::TopRight)),
+        (true, _, _, true) => Some(ScreenZone::Corner(Corner::BottomLeft)),
+        (_, true, _, true) => Some(ScreenZone::Corner(Corner::BottomRight)),
+        (_, _, true, _) => Some(ScreenZone::Edge(Edge::Top)),
+        (_, _, _, true) => Some(ScreenZone::Edge(Edge::Bottom)),
+        (true, _, _, _) => Some(ScreenZone::Edge(Edge::Left)),
+        (_, true, _, _) => Some(ScreenZone::Edge(Edge::Right)),
+        _ => None,
+    }
+}
+
+/// Tracks how long the cursor has rested in a screen zone and fires each zone once per visit.
+#[cfg(any(windows, test))]
+#[derive(Debug, Default)]
+pub struct DwellTracker {
+    current: Option<(ScreenZone, Instant)>,
+    fired: bool,
+}
+
+#[cfg(any(windows, test))]
+impl DwellTracker {
+    /// Feeds the latest cursor zone and returns the zone whose dwell time has just elapsed.
+    ///
+    /// `dwell_for` maps a zone to its configured dwell time, or `None` when nothing is bound.
+    pub fn update(
+        &mut self,
+        zone: Option<ScreenZone>,
+        now: Instant,
+        dwell_for: impl Fn(ScreenZone) -> Option<Duration>,
+    ) -> Option<ScreenZone> {
+        let Some(zone) = zone else {
+            self.current = None;
+            self.fired = false;
+            return None;
+        };
+
+        match self.current {
+            Some((current, _)) if current == zone => {}
+            _ => {
+                self.current = Some((zone, now));
+                self.fired = false;
+            }
+        }
+
+        let (_, entered_at) = self.current?;
+        let dwell = dwell_for(zone)?;
+        if self.fired || now.duration_since(entered_at) < dwell {
+            return None;
+        }
+        self.fired = true;
+        Some(zone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1920x1080 monitor placed left of and above the primary one.
+    const SECONDARY: Rect = Rect {
+        left: -1920,
+        top: -1080,
+        right: 0,
+        bottom: 0,
+    };
+
+    const PRIMARY: Rect = Rect {
+        left: 0,
+        top: 0,
+        right: 2560,
+        bottom: 1440,
+    };
+
+    #[test]
+    fn zone_at_finds_corners_and_edges_on_a_negative_origin() {
+        let cases = [
+            (-1920, -1080, Some(ScreenZone::Corner(Corner::TopLeft))),
+            (-1919, -1079, Some(ScreenZone::Corner(Corner::TopLeft))),
+            (-1, -1080, Some(ScreenZone::Corner(Corner::TopRight))),
+            (-1920, -1, Some(ScreenZone::Corner(Corner::BottomLeft))),
+            (-1, -1, Some(ScreenZone::Corner(Corner::BottomRight))),
+            (-2, -2, Some(ScreenZone::Corner(Corner::BottomRight))),
+            (-960, -1080, Some(ScreenZone::Edge(Edge::Top))),
+            (-960, -1, Some(ScreenZone::Edge(Edge::Bottom))),
+            (-1920, -540, Some(ScreenZone::Edge(Edge::Left))),
+            (-1, -540, Some(ScreenZone::Edge(Edge::Right))),
+            (-1918, -540, None),
+            (-3, -540, None),
+            (-960, -540, None),
+        ];
+        for (x, y, expected) in cases {
+            assert_eq!(zone_at(x, y, SECONDARY), expected, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn zone_at_ignores_points_on_other_monitors() {
+        assert_eq!(zone_at(0, 0, SECONDARY), None);
+        assert_eq!(zone_at(-1, 0, SECONDARY), None);
+        assert_eq!(zone_at(-1921, -540, SECONDARY), None);
+        assert_eq!(
+            zone_at(0, 0, PRIMARY),
+            Some(ScreenZone::Corner(Corner::TopLeft))
+        );
+        assert_eq!(
+            zone_at(2559, 1439, PRIMARY),
+            Some(ScreenZone::Corner(Corner::BottomRight))
+        );
+        assert_eq!(zone_at(2560, 720, PRIMARY), None);
+    }
+
+    /// Binds every zone with the default 250ms dwell.
+    const FIXED_DWELL: fn(ScreenZone) -> Option<Duration> = |_| Some(DEFAULT_DWELL);
+
+    const TOP_LEFT: ScreenZone = ScreenZone::Corner(Corner::TopLeft);
+
+    #[test]
+    fn dwell_does_not_fire_before_the_threshold() {
+        let start = Instant::now();
+        let mut tracker = DwellTracker::default();
+        assert_eq!(tracker.update(Some(TOP_LEFT), start, FIXED_DWELL), None);
+        let almost = start + Duration::from_millis(249);
+        assert_eq!(tracker.update(Some(TOP_LEFT), almost, FIXED_DWELL), None);
+    }
+
+    #[test]
+    fn dwell_fires_once_per_visit() {
+        let start = Instant::now();
+        let mut tracker = DwellTracker::default();
+        tracker.update(Some(TOP_LEFT), start, FIXED_DWELL);
+        let elapsed = start + Duration::from_millis(250);
+        assert_eq!(
+            tracker.update(Some(TOP_LEFT), elapsed, FIXED_DWELL),
+            Some(TOP_LEFT)
+        );
+        let later = start + Duration::from_secs(5);
+        assert_eq!(tracker.update(Some(TOP_LEFT), later, FIXED_DWELL), None);
+    }
+
+    #[test]
+    fn dwell_rearms_after_leaving_the_zone() {
+        let start = Instant::now();
+        let mut tracker = DwellTracker::default();
+        tracker.update(Some(TOP_LEFT), start, FIXED_DWELL);
+        let fired = start + Duration::from_millis(300);
+        assert_eq!(
+            tracker.update(Some(TOP_LEFT), fired, FIXED_DWELL),
+            Some(TOP_LEFT)
+        );
+
+        let left = fired + Duration::from_millis(10);
+        assert_eq!(tracker.update(None, left, FIXED_DWELL), None);
+        let back = left + Duration::from_millis(10);
+        assert_eq!(tracker.update(Some(TOP_LEFT), back, FIXED_DWELL), None);
+        let again = back + Duration::from_millis(250);
+        assert_eq!(
+            tracker.update(Some(TOP_LEFT), again, FIXED_DWELL),
+            Some(TOP_LEFT)
+        );
+    }
+
+    #[test]
+    fn dwell_restarts_when_moving_to_another_zone() {
+        let start = Instant::now();
+        let mut tracker = DwellTracker::default();
+        tracker.update(Some(TOP_LEFT), start, FIXED_DWELL);
+        let top = ScreenZone::Edge(Edge::Top);
+        let moved = start + Duration::from_millis(200);
+        assert_eq!(tracker.update(Some(top), moved, FIXED_DWELL), None);
+        let early = start + Duration::from_millis(300);
+        assert_eq!(tracker.update(Some(top), early, FIXED_DWELL), None);
+        let elapsed = moved + Duration::from_millis(250);
+        assert_eq!(tracker.update(Some(top), elapsed, FIXED_DWELL), Some(top));
+    }
+
+    #[test]
+    fn unbound_zones_never_fire() {
+        let start = Instant::now();
+        let mut tracker = DwellTracker::default();
+        tracker.update(Some(TOP_LEFT), start, |_| None);
+        let later = start + Duration::from_secs(5);
+        assert_eq!(tracker.update(Some(TOP_LEFT), later, |_| None), None);
+    }
+}
@@ -1,21 +1,61 @@
 use directories_next::ProjectDirs;
 use eyre::bail;
+use facet::Facet;
 use std::env;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 pub const APP_HOME_ENV_VAR: &str = "TB_HOME_DIR";
 pub const APP_CACHE_ENV_VAR: &str = "TB_CACHE_DIR";
+pub const MACHINE_HOME_ENV_VAR: &str = "TB_MACHINE_DIR";
+/// A file with this name beside the executable switches tb to portable mode.
+pub const PORTABLE_MARKER: &str = "tb.portable";
+/// Settings file, under the app home and the machine home.
+pub const CONFIG_FILE: &str = "config.toml";
+/// Previous versions of the config file, under the app home.
+pub const SNAPSHOT_DIR: &str = "snapshots";
+/// Log directory under the cache home.
+pub const LOG_DIR: &str = "logs";
+/// Runtime state kept between runs, under the app home.
+pub const STATE_FILE: &str = "state.json";
+/// Every taskbar change, one JSON object per line, under the cache; read by `tb stats`.
+pub const EVENTS_FILE: &str = "events.jsonl";
+
+static PORTABLE_FLAG: OnceLock<bool> = OnceLock::new();
+static PORTABLE_ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// How a directory was chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathSource {
+    Env(&'static str),
+    Portable,
+    Default,
+}
+
+impl fmt::Display for PathSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSource::Env(name) => write!(f, "env {name}"),
+            PathSource::Portable => write!(f, "portable"),
+            PathSource::Default => write!(f, "default"),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
-pub struct AppHome(PathBuf);
+pub struct AppHome(PathBuf, PathSource);
 
 impl AppHome {
     pub fn resolve() -> eyre::Result<Self> {
         if let Ok(path) = env::var(APP_HOME_ENV_VAR) {
-            return Ok(Self(PathBuf::from(path)));
+            return Ok(Self(PathBuf::from(path), PathSource::Env(APP_HOME_ENV_VAR)));
+        }
+        if let Some(root) = portable_root() {
+            return Ok(Self(root.join("config"), PathSource::Portable));
         }
         if let Some(dirs) = ProjectDirs::from("", "teamdman", "tb") {
-            return Ok(Self(dirs.config_dir().to_path_buf()));
+            return Ok(Self(dirs.config_dir().to_path_buf(), PathSource::Default));
         }
         bail!("Could not determine app home directory")
     }
@@ -28,18 +68,25 @@ impl AppHome {
     pub fn path(&self) -> &Path {
         &self.0
     }
+
+    pub fn source(&self) -> PathSource {
+        self.1
+    }
 }
 
 #[derive(Clone, Debug)]
-pub struct CacheHome(PathBuf);
+pub struct CacheHome(PathBuf, PathSource);
 
 impl CacheHome {
     pub fn resolve() -> eyre::Result<Self> {
         if let Ok(path) = env::var(APP_CACHE_ENV_VAR) {
-            return Ok(Self(PathBuf::from(path)));
+            return Ok(Self(PathBuf::from(path), PathSource::Env(APP_CACHE_ENV_VAR)));
+        }
+        if let Some(root) = portable_root() {
+            return Ok(Self(root.join("cache"), PathSource::Portable));
         }
         if let Some(dirs) = ProjectDirs::from("", "teamdman", "tb") {
-            return Ok(Self(dirs.cache_dir().to_path_buf()));
+            return Ok(Self(dirs.cache_dir().to_path_buf(), PathSource::Default));
         }
         bail!("Could not determine app cache directory")
     }
@@ -52,6 +99,82 @@ impl CacheHome {
     pub fn path(&self) -> &Path {
         &self.0
     }
+
+    pub fn source(&self) -> PathSource {
+        self.1
+    }
+}
+
+/// Machine-wide settings directory shared by every user, `%ProgramData%\tb` by default.
+#[derive(Clone, Debug)]
+pub struct MachineHome(PathBuf, PathSource);
+
+impl MachineHome {
+    pub fn resolve() -> eyre::Result<Self> {
+        if let Ok(path) = env::var(MACHINE_HOME_ENV_VAR) {
+            return Ok(Self(PathBuf::from(path), PathSource::Env(MACHINE_HOME_ENV_VAR)));
+        }
+        if portable_root().is_some() {
+            bail!("Machine-wide config is not used in portable mode")
+        }
+        if let Ok(program_data) = env::var("ProgramData") {
+            return Ok(Self(PathBuf::from(program_data).join("tb"), PathSource::Default));
+        }
+        bail!("Could not determine machine config directory")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn source(&self) -> PathSource {
+        self.1
+    }
+}
+
+/// Records whether `--portable` was passed. Must be called before any directory is resolved.
+pub fn set_portable(portable: bool) {
+    let _ = PORTABLE_FLAG.set(portable);
+}
+
+/// The directory holding the executable when running portable, i.e. with `--portable` or a
+/// [`PORTABLE_MARKER`] file beside the exe; `None` otherwise.
+pub fn portable_root() -> Option<&'static Path> {
+    PORTABLE_ROOT
+        .get_or_init(|| {
+            let exe_dir = env::current_exe().ok()?.parent()?.to_path_buf();
+            let flagged = PORTABLE_FLAG.get().copied().unwrap_or(false);
+            (flagged || exe_dir.join(PORTABLE_MARKER).is_file()).then_some(exe_dir)
+        })
+        .as_deref()
+}
+
+/// Serializes the unit tests that read or write tb's directories.
+#[cfg(test)]
+static SCRATCH_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Points the app home, cache and machine directories at empty scratch directories, and the
+/// taskbar at the fake one, for unit tests. Every caller shares the same directories, which are
+/// emptied on each call, so hold the returned guard for the whole test.
+#[cfg(test)]
+pub fn scratch_dirs() -> std::sync::MutexGuard<'static, ()> {
+    static CONFIGURED: std::sync::Once = std::sync::Once::new();
+    let guard = SCRATCH_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let root = env::temp_dir().join(format!("tb-unit-{}", std::process::id()));
+    CONFIGURED.call_once(|| {
+        // SAFETY: the tests that read these variables take `SCRATCH_LOCK` first, and nothing in
+        // the test binary reads the environment outside of `std::env`.
+        unsafe {
+            env::set_var(APP_HOME_ENV_VAR, root.join("home"));
+            env::set_var(APP_CACHE_ENV_VAR, root.join("cache"));
+            env::set_var(MACHINE_HOME_ENV_VAR, root.join("machine"));
+            env::set_var(crate::taskbar::FAKE_TASKBAR_ENV_VAR, "1");
+        }
+    });
+    let _ = std::fs::remove_dir_all(&root);
+    guard
 }
 
 pub fn app_home() -> eyre::Result<AppHome> {
@@ -61,3 +184,99 @@ pub fn app_home() -> eyre::Result<AppHome> {
 pub fn cache_home() -> eyre::Result<CacheHome> {
     CacheHome::resolve()
 }
+
+pub fn machine_home() -> eyre::Result<MachineHome> {
+    MachineHome::resolve()
+}
+
+pub fn log_dir() -> eyre::Result<PathBuf> {
+    Ok(cache_home()?.path().join(LOG_DIR))
+}
+
+pub fn state_path() -> eyre::Result<PathBuf> {
+    Ok(app_home()?.path().join(STATE_FILE))
+}
+
+pub fn events_path() -> eyre::Result<PathBuf> {
+    Ok(cache_home()?.path().join(EVENTS_FILE))
+}
+
+/// A directory or file tb reads or writes, as reported by `tb paths`.
+#[derive(Facet, Debug)]
+pub struct KnownPath {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub exists: bool,
+    /// Bytes, summed over every file for directories; absent when the path does not exist.
+    pub size: Option<u64>,
+    pub source: String,
+}
+
+/// Every location tb uses, without creating any of them.
+pub fn known_paths() -> eyre::Result<Vec<KnownPath>> {
+    let home = app_home()?;
+    let cache = cache_home()?;
+    let mut paths = vec![
+        known_path("config dir", home.path(), true, home.source()),
+        known_path("config file", &home.path().join(CONFIG_FILE), false, home.source()),
+        known_path("snapshot dir", &home.path().join(SNAPSHOT_DIR), true, home.source()),
+        known_path("state file", &home.path().join(STATE_FILE), false, home.source()),
+        known_path("cache dir", cache.path(), true, cache.source()),
+        known_path("log dir", &cache.path().join(LOG_DIR), true, cache.source()),
+        known_path("event history", &cache.path().join(EVENTS_FILE), false, cache.source()),
+    ];
+    if let Ok(machine) = machine_home() {
+        paths.push(known_path(
+            "machine config file",
+            &machine.path().join(CONFIG_FILE),
+            false,
+            machine.source(),
+        ));
+    }
+    Ok(paths)
+}
+
+fn known_path(name: &str, path: &Path, is_dir: bool, source: PathSource) -> KnownPath {
+    let exists = path.exists();
+    KnownPath {
+        name: name.to_string(),
+        path: path.display().to_string(),
+        is_dir,
+        exists,
+        size: exists.then(|| size_of(path)),
+        source: source.to_string(),
+    }
+}
+
+/// Size of a file, or of every file below a directory. Unreadable entries count as empty.
+pub fn size_of(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| size_of(&entry.path()))
+            .sum()
+    })
+}
+
+/// Formats a byte count for people, e.g. `1.5 MiB`.
+#[expect(clippy::cast_precision_loss, reason = "display only")]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
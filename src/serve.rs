@@ -0,0 +1,343 @@
+use crate::action::{Action, Source};
+use crate::dispatch;
+use eyre::{Context, Result};
+use facet::Facet;
+use regex::Regex;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+// Error codes from the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// The request was valid but changing or reading the taskbar failed.
+const SERVER_ERROR: i32 = -32000;
+
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?$").expect("valid regex")
+});
+
+/// A request without its `id`, which [`raw_id`] reads so it can be echoed back verbatim.
+#[derive(Facet, Debug, Default)]
+#[facet(default)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    params: Params,
+}
+
+#[derive(Facet, Debug, Default)]
+#[facet(default, rename_all = "camelCase")]
+struct Params {
+    auto_hide: Option<bool>,
+    action: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+#[facet(rename_all = "camelCase")]
+struct StateResult {
+    auto_hide: bool,
+}
+
+#[derive(Facet, Debug)]
+struct ActionsResult {
+    actions: Vec<String>,
+}
+
+#[derive(Facet, Debug)]
+struct SubscribeResult {
+    subscribed: bool,
+}
+
+#[derive(Facet, Debug)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Speaks line-delimited JSON-RPC 2.0 on stdin/stdout until stdin closes.
+///
+/// Methods are `getState`, `setState` (`{"autoHide": bool}`), `toggle`, `listActions`,
+/// `triggerAction` (`{"action": name}`), `subscribe` and `unsubscribe`. Subscribers receive a
+/// `stateChanged` notification whenever auto-hide changes, whoever changed it. Changes go through
+/// [`dispatch::perform`], the same path as `tb toggle`.
+pub fn serve_stdio() -> Result<()> {
+    let subscribed = Arc::new(AtomicBool::new(false));
+    let notify = Arc::clone(&subscribed);
+    dispatch::watch(move |auto_hide| {
+        if !notify.load(Ordering::SeqCst) {
+            return true;
+        }
+        let params = facet_json::to_string(&StateResult { auto_hide });
+        // Stop watching once stdout is gone.
+        send_line(&format!(
+            r#"{{"jsonrpc":"2.0","method":"stateChanged","params":{params}}}"#
+        ))
+        .is_ok()
+    })?;
+
+    for line in io::stdin().lock().lines() {
+        let line = line.wrap_err("Failed to read a request from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(line.trim(), &subscribed) {
+            send_line(&response).wrap_err("Failed to write a response to stdout")?;
+        }
+    }
+    Ok(())
+}
+
+/// The response to one line, or `None` for a notification.
+fn handle_line(line: &str, subscribed: &AtomicBool) -> Option<String> {
+    if skip_value(line).is_none_or(|rest| !rest.trim().is_empty()) {
+        return Some(failure("null", &RpcError::new(PARSE_ERROR, "Parse error")));
+    }
+    // Absent for notifications, which get no response.
+    let id = raw_id(line);
+    if id.is_some_and(|id| id.starts_with(['t', 'f', '{', '['])) {
+        let error = RpcError::new(INVALID_REQUEST, "The id must be a string, a number or null");
+        return Some(failure("null", &error));
+    }
+    let request = match facet_json::from_str::<RpcRequest>(line) {
+        Ok(request) => request,
+        Err(error) => {
+            let error = RpcError::new(INVALID_REQUEST, format!("Invalid request: {error}"));
+            return Some(failure(id.unwrap_or("null"), &error));
+        }
+    };
+    let result = call(&request, subscribed);
+    let id = id?;
+    Some(match result {
+        Ok(result) => format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{result}}}"#),
+        Err(error) => failure(id, &error),
+    })
+}
+
+/// The raw text of the top-level `id` member of a JSON object, or `None` when it has none.
+fn raw_id(line: &str) -> Option<&str> {
+    let mut rest = line.trim_start().strip_prefix('{')?.trim_start();
+    while let Some(after_key) = skip_string(rest) {
+        let key = &rest[..rest.len() - after_key.len()];
+        let value = after_key.trim_start().strip_prefix(':')?.trim_start();
+        let after_value = skip_value(value)?;
+        if key == r#""id""# {
+            return Some(&value[..value.len() - after_value.len()]);
+        }
+        rest = after_value.trim_start().strip_prefix(',')?.trim_start();
+    }
+    None
+}
+
+/// Returns what follows the JSON value `text` starts with, or `None` when it isn't one.
+fn skip_value(text: &str) -> Option<&str> {
+    let text = text.trim_start();
+    match text.chars().next()? {
+        '{' => skip_items(&text[1..], '}', |member| {
+            let rest = skip_string(member.trim_start())?;
+            skip_value(rest.trim_start().strip_prefix(':')?)
+        }),
+        '[' => skip_items(&text[1..], ']', skip_value),
+        '"' => skip_string(text),
+        _ => {
+            let end = text
+                .find(|c: char| !c.is_ascii_alphanumeric() && !matches!(c, '+' | '-' | '.'))
+                .unwrap_or(text.len());
+            let token = &text[..end];
+            (matches!(token, "true" | "false" | "null") || NUMBER.is_match(token))
+                .then_some(&text[end..])
+        }
+    }
+}
+
+/// Skips the comma-separated items of an object or array up to and including `close`.
+fn skip_items(text: &str, close: char, item: impl Fn(&str) -> Option<&str>) -> Option<&str> {
+    if let Some(rest) = text.trim_start().strip_prefix(close) {
+        return Some(rest);
+    }
+    let mut rest = text;
+    loop {
+        rest = item(rest)?.trim_start();
+        if let Some(after) = rest.strip_prefix(close) {
+            return Some(after);
+        }
+        rest = rest.strip_prefix(',')?;
+    }
+}
+
+/// Returns what follows the JSON string `text` starts with.
+fn skip_string(text: &str) -> Option<&str> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some(&text[index + 2..]),
+            '\\' => {
+                chars.next()?;
+            }
+            c if c < ' ' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Runs one method and returns its result as JSON.
+fn call(request: &RpcRequest, subscribed: &AtomicBool) -> Result<String, RpcError> {
+    if request.jsonrpc != "2.0" {
+        return Err(RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported"));
+    }
+    match request.method.as_str() {
+        "getState" => state_result(dispatch::state()),
+        "setState" => {
+            let auto_hide = request.params.auto_hide.ok_or_else(|| {
+                RpcError::new(INVALID_PARAMS, "setState needs params.autoHide")
+            })?;
+            state_result(dispatch::perform(Action::for_state(auto_hide), Source::Api))
+        }
+        "toggle" => state_result(dispatch::perform(Action::Toggle, Source::Api)),
+        "listActions" => Ok(facet_json::to_string(&ActionsResult {
+            actions: Action::ALL
+                .iter()
+                .map(|action| action.name().to_string())
+                .collect(),
+        })),
+        "triggerAction" => {
+            let name = request.params.action.as_deref().ok_or_else(|| {
+                RpcError::new(INVALID_PARAMS, "triggerAction needs params.action")
+            })?;
+            let action = Action::parse(name)
+                .map_err(|error| RpcError::new(INVALID_PARAMS, error.to_string()))?;
+            state_result(dispatch::perform(action, Source::Api))
+        }
+        "subscribe" | "unsubscribe" => {
+            let subscribe = request.method == "subscribe";
+            subscribed.store(subscribe, Ordering::SeqCst);
+            Ok(facet_json::to_string(&SubscribeResult {
+                subscribed: subscribe,
+            }))
+        }
+        other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{other}'"))),
+    }
+}
+
+fn state_result(state: Result<bool>) -> Result<String, RpcError> {
+    state
+        .map(|auto_hide| facet_json::to_string(&StateResult { auto_hide }))
+        .map_err(|error| RpcError::new(SERVER_ERROR, format!("{error:#}")))
+}
+
+fn failure(id: &str, error: &RpcError) -> String {
+    let error = facet_json::to_string(error);
+    format!(r#"{{"jsonrpc":"2.0","id":{id},"error":{error}}}"#)
+}
+
+/// Writes one message; the stdout lock keeps notifications from the watcher thread whole.
+fn send_line(line: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{line}")?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths;
+
+    fn respond(line: &str) -> Option<String> {
+        handle_line(line, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn get_state_answers_with_the_state() {
+        let _dirs = paths::scratch_dirs();
+        let response = respond(r#"{"jsonrpc":"2.0","id":1,"method":"getState"}"#).unwrap();
+        assert!(
+            response.starts_with(r#"{"jsonrpc":"2.0","id":1,"result":{"autoHide":"#),
+            "{response}"
+        );
+    }
+
+    #[test]
+    fn errors_carry_their_code_and_id() {
+        for (line, id, code) in [
+            (
+                r#"{"jsonrpc":"2.0","id":2,"method":"setState"}"#,
+                "2",
+                INVALID_PARAMS,
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":3,"method":"fly"}"#,
+                "3",
+                METHOD_NOT_FOUND,
+            ),
+            (
+                r#"{"jsonrpc":"1.0","id":4,"method":"getState"}"#,
+                "4",
+                INVALID_REQUEST,
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":5,"method":[]}"#,
+                "5",
+                INVALID_REQUEST,
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":{},"method":"getState"}"#,
+                "null",
+                INVALID_REQUEST,
+            ),
+            (r#"["getState"]"#, "null", INVALID_REQUEST),
+            (
+                r#"{"jsonrpc":"2.0","id":6,"method":"getState""#,
+                "null",
+                PARSE_ERROR,
+            ),
+            (
+                r#"{"jsonrpc":"2.0","id":07,"method":"getState"}"#,
+                "null",
+                PARSE_ERROR,
+            ),
+            ("getState", "null", PARSE_ERROR),
+        ] {
+            let response = respond(line).unwrap();
+            let expected = format!(r#"{{"jsonrpc":"2.0","id":{id},"error":{{"code":{code},"#);
+            assert!(response.starts_with(&expected), "{line:?}: {response}");
+        }
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        for line in [
+            r#"{"jsonrpc":"2.0","method":"subscribe"}"#,
+            r#"{"jsonrpc":"2.0","method":"fly"}"#,
+        ] {
+            assert_eq!(respond(line), None, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn ids_are_echoed_verbatim() {
+        for id in [
+            r#""abc""#,
+            r#""a\"b""#,
+            "-1.5e3",
+            "18446744073709551616",
+            "null",
+        ] {
+            let line = format!(r#"{{"jsonrpc": "2.0", "id": {id}, "method": "subscribe"}}"#);
+            let expected =
+                format!(r#"{{"jsonrpc":"2.0","id":{id},"result":{{"subscribed":true}}}}"#);
+            assert_eq!(respond(&line), Some(expected), "{id:?}");
+        }
+    }
+}
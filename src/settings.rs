@@ -0,0 +1,670 @@
+use crate::action::Action;
+use crate::condition::Condition;
+use crate::config::{self, Config, LOG_FORMATS, LOG_LEVELS};
+use crate::keymap::Trigger;
+use eyre::{Context, Result, bail, eyre};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Settings that have a single value, addressable by dotted key and overridable from `TB_*` env vars.
+pub const SCALAR_KEYS: [&str; 13] = [
+    "startup.auto_hide",
+    "startup.show_logs",
+    "logging.level",
+    "logging.format",
+    "logging.max_file_size_mb",
+    "logging.max_files",
+    "policies.restore_on_exit",
+    "policies.enforce",
+    "cache.retention_days",
+    "hooks.on_hide",
+    "hooks.on_show",
+    "hooks.on_toggle_failed",
+    "hooks.timeout_seconds",
+];
+
+const BINDING_FIELDS: [&str; 3] = ["trigger", "action", "condition"];
+
+static CLI_OVERRIDES: OnceLock<Vec<CliOverride>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Integer(u32),
+    Text(String),
+}
+
+impl Value {
+    pub fn to_toml(&self) -> String {
+        match self {
+            Value::Bool(value) => value.to_string(),
+            Value::Integer(value) => value.to_string(),
+            Value::Text(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::Text(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// A configuration layer. Later variants take precedence over earlier ones.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Default,
+    Machine(PathBuf),
+    User(PathBuf),
+    Env(String),
+    Cli(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Machine(path) => write!(f, "machine {}", path.display()),
+            Source::User(path) => write!(f, "user {}", path.display()),
+            Source::Env(name) => write!(f, "env {name}"),
+            Source::Cli(flag) => write!(f, "cli {flag}"),
+        }
+    }
+}
+
+/// A setting value supplied by a command-line flag, e.g. `--debug` for `logging.level`.
+#[derive(Debug, Clone)]
+pub struct CliOverride {
+    pub key: String,
+    pub value: String,
+    pub flag: String,
+}
+
+impl CliOverride {
+    /// The arguments that give a spawned tb the same override.
+    #[cfg(any(windows, test))]
+    pub fn to_args(&self) -> Vec<String> {
+        match self.flag.as_str() {
+            "--debug" => vec![self.flag.clone()],
+            "--log-format" => vec![self.flag.clone(), self.value.clone()],
+            _ => vec!["--set".to_string(), format!("{}={}", self.key, self.value)],
+        }
+    }
+}
+
+/// What one layer says about a setting; `present` is false when the layer leaves it alone.
+#[derive(Debug)]
+pub struct LayerValue {
+    pub source: Source,
+    pub present: bool,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug)]
+pub struct Resolved {
+    pub key: String,
+    pub value: Option<Value>,
+    pub source: Source,
+    /// Every layer in precedence order, lowest first.
+    pub chain: Vec<LayerValue>,
+}
+
+#[derive(Debug)]
+pub struct Resolution {
+    pub config: Config,
+    pub entries: Vec<Resolved>,
+}
+
+/// Registers the settings overridden on the command line. Only the first call takes effect.
+pub fn set_cli_overrides(overrides: Vec<CliOverride>) {
+    let _ = CLI_OVERRIDES.set(overrides);
+}
+
+/// The settings overridden on the command line of this process.
+pub fn cli_overrides() -> &'static [CliOverride] {
+    CLI_OVERRIDES.get().map_or(&[], Vec::as_slice)
+}
+
+pub fn env_var_name(key: &str) -> String {
+    format!("TB_{}", key.replace('.', "_").to_ascii_uppercase())
+}
+
+/// Every key present in `config`, including `bindings.<n>.<field>` for each binding.
+pub fn keys(config: &Config) -> Vec<String> {
+    let mut keys = vec!["version".to_string()];
+    keys.extend(SCALAR_KEYS.iter().map(ToString::to_string));
+    for index in 0..config.bindings.len() {
+        keys.extend(
+            BINDING_FIELDS
+                .iter()
+                .map(|field| format!("bindings.{index}.{field}")),
+        );
+    }
+    keys
+}
+
+/// Splits a dotted key into the TOML table it lives in and its field name.
+pub fn location(key: &str) -> Result<(Option<(&str, usize)>, &str)> {
+    if key == "version" {
+        return Ok((None, key));
+    }
+    if let Some((index, field)) = binding_key(key)? {
+        return Ok((Some(("bindings", index)), field));
+    }
+    if SCALAR_KEYS.contains(&key)
+        && let Some((table, field)) = key.split_once('.')
+    {
+        return Ok((Some((table, 0)), field));
+    }
+    bail!("Unknown setting '{key}'")
+}
+
+fn binding_key(key: &str) -> Result<Option<(usize, &str)>> {
+    let Some(rest) = key.strip_prefix("bindings.") else {
+        return Ok(None);
+    };
+    let (index, field) = rest
+        .split_once('.')
+        .ok_or_else(|| eyre!("Binding keys look like 'bindings.<index>.<field>'"))?;
+    let index = index
+        .parse::<usize>()
+        .wrap_err_with(|| format!("Invalid binding index '{index}'"))?;
+    if !BINDING_FIELDS.contains(&field) {
+        bail!(
+            "Unknown binding field '{field}', expected one of: {}",
+            BINDING_FIELDS.join(", ")
+        )
+    }
+    Ok(Some((index, field)))
+}
+
+pub fn get(config: &Config, key: &str) -> Result<Option<Value>> {
+    Ok(match key {
+        "version" => Some(Value::Integer(config.version)),
+        "startup.auto_hide" => config.startup.auto_hide.map(Value::Bool),
+        "startup.show_logs" => Some(Value::Bool(config.startup.show_logs)),
+        "logging.level" => Some(Value::Text(config.logging.level.clone())),
+        "logging.format" => Some(Value::Text(config.logging.format.clone())),
+        "logging.max_file_size_mb" => Some(Value::Integer(config.logging.max_file_size_mb)),
+        "logging.max_files" => Some(Value::Integer(config.logging.max_files)),
+        "policies.restore_on_exit" => Some(Value::Bool(config.policies.restore_on_exit)),
+        "policies.enforce" => Some(Value::Bool(config.policies.enforce)),
+        "cache.retention_days" => Some(Value::Integer(config.cache.retention_days)),
+        "hooks.on_hide" => config.hooks.on_hide.clone().map(Value::Text),
+        "hooks.on_show" => config.hooks.on_show.clone().map(Value::Text),
+        "hooks.on_toggle_failed" => config.hooks.on_toggle_failed.clone().map(Value::Text),
+        "hooks.timeout_seconds" => Some(Value::Integer(config.hooks.timeout_seconds)),
+        _ => {
+            let (index, field) = binding_key(key)?.ok_or_else(|| eyre!("Unknown setting '{key}'"))?;
+            let binding = config
+                .bindings
+                .get(index)
+                .ok_or_else(|| eyre!("There is no binding {index}"))?;
+            match field {
+                "trigger" => Some(Value::Text(binding.trigger.clone())),
+                "action" => Some(Value::Text(binding.action.clone())),
+                _ => binding.condition.clone().map(Value::Text),
+            }
+        }
+    })
+}
+
+/// Parses `raw` for `key`, stores it in `config` and returns the normalized value.
+pub fn set(config: &mut Config, key: &str, raw: &str) -> Result<Value> {
+    match key {
+        "version" => bail!("'version' is managed by tb and cannot be set"),
+        "startup.auto_hide" => {
+            let value = parse_bool(raw)?;
+            config.startup.auto_hide = Some(value);
+            Ok(Value::Bool(value))
+        }
+        "startup.show_logs" => {
+            let value = parse_bool(raw)?;
+            config.startup.show_logs = value;
+            Ok(Value::Bool(value))
+        }
+        "logging.level" => {
+            let level = raw.trim().to_ascii_lowercase();
+            if !LOG_LEVELS.contains(&level.as_str()) {
+                bail!("Log level must be one of {}, found '{raw}'", LOG_LEVELS.join(", "))
+            }
+            config.logging.level.clone_from(&level);
+            Ok(Value::Text(level))
+        }
+        "logging.format" => {
+            let format = raw.trim().to_ascii_lowercase();
+            if !LOG_FORMATS.contains(&format.as_str()) {
+                bail!("Log format must be one of {}, found '{raw}'", LOG_FORMATS.join(", "))
+            }
+            config.logging.format.clone_from(&format);
+            Ok(Value::Text(format))
+        }
+        "logging.max_file_size_mb" => {
+            let value = parse_number(raw)?;
+            config.logging.max_file_size_mb = value;
+            Ok(Value::Integer(value))
+        }
+        "logging.max_files" => {
+            let value = parse_number(raw)?;
+            config.logging.max_files = value;
+            Ok(Value::Integer(value))
+        }
+        "policies.restore_on_exit" => {
+            let value = parse_bool(raw)?;
+            config.policies.restore_on_exit = value;
+            Ok(Value::Bool(value))
+        }
+        "policies.enforce" => {
+            let value = parse_bool(raw)?;
+            config.policies.enforce = value;
+            Ok(Value::Bool(value))
+        }
+        "cache.retention_days" => {
+            let value = parse_number(raw)?;
+            config.cache.retention_days = value;
+            Ok(Value::Integer(value))
+        }
+        "hooks.on_hide" | "hooks.on_show" | "hooks.on_toggle_failed" => {
+            let command = raw.trim().to_string();
+            if command.is_empty() {
+                bail!("A hook command cannot be empty; use `tb config unset {key}` to remove it")
+            }
+            let hook = match key {
+                "hooks.on_hide" => &mut config.hooks.on_hide,
+                "hooks.on_show" => &mut config.hooks.on_show,
+                _ => &mut config.hooks.on_toggle_failed,
+            };
+            *hook = Some(command.clone());
+            Ok(Value::Text(command))
+        }
+        "hooks.timeout_seconds" => {
+            let value = parse_number(raw)?;
+            config.hooks.timeout_seconds = value;
+            Ok(Value::Integer(value))
+        }
+        _ => {
+            let (index, field) = binding_key(key)?.ok_or_else(|| eyre!("Unknown setting '{key}'"))?;
+            let binding = config
+                .bindings
+                .get_mut(index)
+                .ok_or_else(|| eyre!("There is no binding {index}; add bindings with `tb hotkey`"))?;
+            let value = match field {
+                "trigger" => Trigger::parse(raw)?.to_string(),
+                "action" => Action::parse(raw)?.name().to_string(),
+                _ => Condition::parse(raw)?.to_string(),
+            };
+            match field {
+                "trigger" => binding.trigger.clone_from(&value),
+                "action" => binding.action.clone_from(&value),
+                _ => binding.condition = Some(value.clone()),
+            }
+            Ok(Value::Text(value))
+        }
+    }
+}
+
+/// Resets `key` to its default in `config`.
+pub fn unset(config: &mut Config, key: &str) -> Result<()> {
+    let defaults = Config::default();
+    match key {
+        "startup.auto_hide" => config.startup.auto_hide = defaults.startup.auto_hide,
+        "startup.show_logs" => config.startup.show_logs = defaults.startup.show_logs,
+        "logging.level" => config.logging.level = defaults.logging.level,
+        "logging.format" => config.logging.format = defaults.logging.format,
+        "logging.max_file_size_mb" => {
+            config.logging.max_file_size_mb = defaults.logging.max_file_size_mb;
+        }
+        "logging.max_files" => config.logging.max_files = defaults.logging.max_files,
+        "policies.restore_on_exit" => {
+            config.policies.restore_on_exit = defaults.policies.restore_on_exit;
+        }
+        "policies.enforce" => config.policies.enforce = defaults.policies.enforce,
+        "cache.retention_days" => config.cache.retention_days = defaults.cache.retention_days,
+        "hooks.on_hide" => config.hooks.on_hide = None,
+        "hooks.on_show" => config.hooks.on_show = None,
+        "hooks.on_toggle_failed" => config.hooks.on_toggle_failed = None,
+        "hooks.timeout_seconds" => config.hooks.timeout_seconds = defaults.hooks.timeout_seconds,
+        _ => match binding_key(key)? {
+            Some((index, "condition")) => {
+                config
+                    .bindings
+                    .get_mut(index)
+                    .ok_or_else(|| eyre!("There is no binding {index}"))?
+                    .condition = None;
+            }
+            Some(_) => bail!("'{key}' is required; remove the binding with `tb config edit` instead"),
+            None => bail!("'{key}' cannot be unset"),
+        },
+    }
+    Ok(())
+}
+
+fn parse_number(raw: &str) -> Result<u32> {
+    raw.trim()
+        .parse::<u32>()
+        .wrap_err_with(|| format!("Expected a whole number, found '{raw}'"))
+}
+
+fn parse_bool(raw: &str) -> Result<bool> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => bail!("Expected true or false, found '{raw}'"),
+    }
+}
+
+struct FileLayer {
+    source: Source,
+    config: Config,
+    raw: String,
+}
+
+/// Builds the effective configuration by layering, from lowest to highest precedence:
+/// built-in defaults, the machine-wide config file, the user config file, `TB_*` environment
+/// variables and command-line flags.
+///
+/// Scalar settings resolve key by key. `bindings` is taken as a whole from the highest file
+/// layer that defines any, since merging lists entry by entry would be ambiguous.
+pub fn resolve() -> Result<Resolution> {
+    resolve_with(cli_overrides())
+}
+
+fn resolve_with(cli_overrides: &[CliOverride]) -> Result<Resolution> {
+    let mut files = Vec::new();
+    if let Some((config, raw)) = config::read_machine_config_file()? {
+        files.push(FileLayer {
+            source: Source::Machine(config::machine_config_path()?),
+            config,
+            raw,
+        });
+    }
+    if let (config, Some(raw)) = config::read_config_file()? {
+        files.push(FileLayer {
+            source: Source::User(config::config_path()?),
+            config,
+            raw,
+        });
+    }
+
+    let defaults = Config::default();
+    let mut config = Config::default();
+    let mut bindings_source = Source::Default;
+    for file in &files {
+        if file.raw.contains("[[bindings]]") {
+            config.bindings.clone_from(&file.config.bindings);
+            bindings_source = file.source.clone();
+        }
+    }
+
+    let mut entries = Vec::new();
+    for key in keys(&config) {
+        let mut chain = Vec::new();
+        if key.starts_with("bindings.") {
+            chain.push(LayerValue {
+                source: bindings_source.clone(),
+                present: true,
+                value: get(&config, &key)?,
+            });
+        } else {
+            chain.push(LayerValue {
+                source: Source::Default,
+                present: true,
+                value: get(&defaults, &key)?,
+            });
+            for file in &files {
+                let (table, field) = location(&key)?;
+                let present = config::has_key(&file.raw, table, field);
+                chain.push(LayerValue {
+                    source: file.source.clone(),
+                    present,
+                    value: if present { get(&file.config, &key)? } else { None },
+                });
+            }
+        }
+
+        if SCALAR_KEYS.contains(&key.as_str()) {
+            let env_name = env_var_name(&key);
+            let env_value = std::env::var(&env_name).ok();
+            chain.push(LayerValue {
+                source: Source::Env(env_name),
+                present: env_value.is_some(),
+                value: env_value.map(Value::Text),
+            });
+        }
+        for cli in cli_overrides {
+            if cli.key == key {
+                chain.push(LayerValue {
+                    source: Source::Cli(cli.flag.clone()),
+                    present: true,
+                    value: Some(Value::Text(cli.value.clone())),
+                });
+            }
+        }
+
+        for layer in chain.iter_mut().filter(|layer| layer.present) {
+            if let Some(value) = &layer.value {
+                let normalized = assign(&mut config, &key, value)
+                    .wrap_err_with(|| format!("Invalid value for {key} from {}", layer.source))?;
+                layer.value = Some(normalized);
+            }
+        }
+
+        let source = chain
+            .iter()
+            .rev()
+            .find(|layer| layer.present)
+            .map_or(Source::Default, |layer| layer.source.clone());
+        entries.push(Resolved {
+            value: get(&config, &key)?,
+            key,
+            source,
+            chain,
+        });
+    }
+
+    if let Some(unknown) = cli_overrides
+        .iter()
+        .find(|cli| !entries.iter().any(|entry| entry.key == cli.key))
+    {
+        bail!("Unknown setting '{}' in {}", unknown.key, unknown.flag)
+    }
+
+    Ok(Resolution { config, entries })
+}
+
+fn assign(config: &mut Config, key: &str, value: &Value) -> Result<Value> {
+    match (key, value) {
+        ("version", Value::Integer(version)) => {
+            config.version = *version;
+            Ok(value.clone())
+        }
+        _ => set(config, key, &value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths;
+
+    fn write_layer(dir: PathBuf, contents: &str) {
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(paths::CONFIG_FILE), contents).unwrap();
+    }
+
+    fn entry<'a>(resolution: &'a Resolution, key: &str) -> &'a Resolved {
+        resolution
+            .entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .unwrap()
+    }
+
+    fn cli(key: &str, value: &str) -> CliOverride {
+        CliOverride {
+            key: key.to_string(),
+            value: value.to_string(),
+            flag: format!("--set {key}"),
+        }
+    }
+
+    #[test]
+    fn overrides_turn_back_into_arguments() {
+        let cases = [
+            ("logging.level", "debug", "--debug", vec!["--debug"]),
+            (
+                "logging.format",
+                "json",
+                "--log-format",
+                vec!["--log-format", "json"],
+            ),
+            (
+                "cache.retention_days",
+                "3",
+                "--set cache.retention_days",
+                vec!["--set", "cache.retention_days=3"],
+            ),
+        ];
+        for (key, value, flag, args) in cases {
+            let cli = CliOverride {
+                key: key.to_string(),
+                value: value.to_string(),
+                flag: flag.to_string(),
+            };
+            assert_eq!(cli.to_args(), args, "{flag}");
+        }
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let _dirs = paths::scratch_dirs();
+        let machine = paths::machine_home().unwrap().path().to_path_buf();
+        let home = paths::app_home().unwrap().path().to_path_buf();
+        write_layer(
+            machine.clone(),
+            "[logging]\nlevel = \"warn\"\nformat = \"json\"\nmax_files = 3\nmax_file_size_mb = 7\n\n\
+             [cache]\nretention_days = 9\n",
+        );
+        write_layer(
+            home.clone(),
+            "[logging]\nlevel = \"error\"\nformat = \"compact\"\nmax_files = 4\n\n\
+             [[bindings]]\ntrigger = \"Ctrl+Alt+T\"\naction = \"enable\"\n",
+        );
+        // SAFETY: `scratch_dirs` keeps every test that reads the environment out until it returns.
+        unsafe {
+            std::env::set_var("TB_LOGGING_LEVEL", "debug");
+            std::env::set_var("TB_LOGGING_FORMAT", "text");
+        }
+        let resolved = resolve_with(&[
+            cli("logging.level", "trace"),
+            cli("bindings.0.action", "disable"),
+        ]);
+        // SAFETY: as above.
+        unsafe {
+            std::env::remove_var("TB_LOGGING_LEVEL");
+            std::env::remove_var("TB_LOGGING_FORMAT");
+        }
+        let resolved = resolved.unwrap();
+
+        let config = &resolved.config;
+        assert_eq!(config.hooks.timeout_seconds, 30, "default");
+        assert_eq!(config.cache.retention_days, 9, "machine");
+        assert_eq!(config.logging.max_file_size_mb, 7, "machine");
+        assert_eq!(config.logging.max_files, 4, "user over machine");
+        assert_eq!(config.logging.format, "text", "env over user");
+        assert_eq!(config.logging.level, "trace", "cli over env");
+        assert_eq!(
+            config.bindings,
+            [config::BindingConfig {
+                trigger: "Ctrl+Alt+T".to_string(),
+                action: "disable".to_string(),
+                condition: None,
+            }],
+            "cli over the user's bindings"
+        );
+
+        let machine_file = format!("machine {}", machine.join(paths::CONFIG_FILE).display());
+        let user_file = format!("user {}", home.join(paths::CONFIG_FILE).display());
+        let cases = [
+            ("hooks.timeout_seconds", "default"),
+            ("cache.retention_days", machine_file.as_str()),
+            ("logging.max_files", user_file.as_str()),
+            ("logging.format", "env TB_LOGGING_FORMAT"),
+            ("logging.level", "cli --set logging.level"),
+            ("bindings.0.trigger", user_file.as_str()),
+            ("bindings.0.action", "cli --set bindings.0.action"),
+        ];
+        for (key, source) in cases {
+            assert_eq!(entry(&resolved, key).source.to_string(), source, "{key}");
+        }
+    }
+
+    #[test]
+    fn explain_lists_every_layer() {
+        let _dirs = paths::scratch_dirs();
+        write_layer(
+            paths::machine_home().unwrap().path().to_path_buf(),
+            "[logging]\nlevel = \"warn\"\n",
+        );
+        write_layer(
+            paths::app_home().unwrap().path().to_path_buf(),
+            "[cache]\nretention_days = 1\n",
+        );
+        let resolved = resolve_with(&[cli("logging.level", "DEBUG")]).unwrap();
+
+        let level = entry(&resolved, "logging.level");
+        let chain = level
+            .chain
+            .iter()
+            .map(|layer| {
+                let source = layer.source.to_string();
+                let kind = source.split(' ').next().unwrap().to_string();
+                (kind, layer.present, layer.value.clone())
+            })
+            .collect::<Vec<_>>();
+        let text = |value: &str| Some(Value::Text(value.to_string()));
+        assert_eq!(
+            chain,
+            [
+                ("default".to_string(), true, text("info")),
+                ("machine".to_string(), true, text("warn")),
+                ("user".to_string(), false, None),
+                ("env".to_string(), false, None),
+                ("cli".to_string(), true, text("debug")),
+            ]
+        );
+        assert_eq!(level.value, text("debug"));
+    }
+
+    #[test]
+    fn invalid_layers_name_their_source() {
+        let _dirs = paths::scratch_dirs();
+        let cases = [
+            (
+                "logging.level",
+                "loud",
+                "Invalid value for logging.level from cli",
+            ),
+            (
+                "bindings.0.trigger",
+                "Ctrl+Alt",
+                "Invalid value for bindings.0.trigger from cli",
+            ),
+            (
+                "bindings.3.action",
+                "toggle",
+                "Unknown setting 'bindings.3.action'",
+            ),
+            ("logging.colour", "red", "Unknown setting 'logging.colour'"),
+        ];
+        for (key, value, expected) in cases {
+            let error = resolve_with(&[cli(key, value)]).unwrap_err().to_string();
+            assert!(error.starts_with(expected), "{error}");
+        }
+    }
+}
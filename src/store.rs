@@ -0,0 +1,373 @@
+use eyre::{Context, Result, bail};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::io::FromRawHandle;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(windows)]
+use windows::Win32::Foundation::{HLOCAL, LocalFree};
+#[cfg(windows)]
+use windows::Win32::Security::Authorization::{
+    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+};
+#[cfg(windows)]
+use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{
+    CREATE_NEW, CreateFileW, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_WRITE, FILE_SHARE_NONE,
+};
+#[cfg(windows)]
+use windows::core::{HSTRING, w};
+
+/// How many previous versions of a file [`snapshot`] keeps.
+pub const SNAPSHOT_COUNT: usize = 5;
+
+/// Numbers the temporary files of [`replace`], so threads writing the same file at once do not
+/// share one.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An exclusive advisory lock on a lock file, released when dropped.
+///
+/// Every tb process takes the same lock before a read-modify-write cycle, so a CLI command and
+/// the tray cannot interleave their writes.
+#[derive(Debug)]
+pub struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Blocks until the lock at `path` is available.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .wrap_err_with(|| format!("Failed to open lock file {}", path.display()))?;
+        file.lock()
+            .wrap_err_with(|| format!("Failed to lock {}", path.display()))?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Fails unless this is the lock on `path`, so a writer cannot be handed some other lock.
+    pub fn ensure_guards(&self, path: &Path) -> Result<()> {
+        if self.path != path {
+            bail!(
+                "Holding the lock {} instead of {}",
+                self.path.display(),
+                path.display()
+            )
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(error) = self.file.unlock() {
+            tracing::warn!("Failed to unlock {}: {error}", self.path.display());
+        }
+    }
+}
+
+/// Replaces `path` with `contents` so readers see either the old or the new file, never a
+/// partial one: the data is written and flushed to a temporary file beside it, then renamed over.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    replace(path, contents, |temp| File::create(temp))
+}
+
+/// [`write_atomic`] for secrets: the file only grants the current user access (mode 0600 on
+/// Unix, an owner-only ACL on Windows) from the moment it exists, before anything is written.
+#[cfg_attr(
+    not(any(feature = "http", test)),
+    expect(dead_code, reason = "only the HTTP API's token is a secret")
+)]
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    replace(path, contents, create_private)
+}
+
+fn replace(path: &Path, contents: &[u8], create: fn(&Path) -> io::Result<File>) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "file".into(), |name| name.to_string_lossy());
+    let temp = path.with_file_name(format!(
+        ".{file_name}.{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+    if let Err(error) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(error).wrap_err_with(|| format!("Failed to write {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Creates `path`, which must not exist yet, readable and writable only by its owner.
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<File> {
+    let _ = std::fs::remove_file(path);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Creates `path`, which must not exist yet, with a protected DACL granting only its owner access.
+#[cfg(windows)]
+fn create_private(path: &Path) -> io::Result<File> {
+    let _ = std::fs::remove_file(path);
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    // SAFETY: the SDDL string is a static wide string and `descriptor` outlives the call.
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            w!("D:P(A;;FA;;;OW)"),
+            SDDL_REVISION_1,
+            &raw mut descriptor,
+            None,
+        )
+    }?;
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    // SAFETY: `attributes` and the descriptor it points to live until the call returns.
+    let handle = unsafe {
+        CreateFileW(
+            &HSTRING::from(path.as_os_str()),
+            FILE_GENERIC_WRITE.0,
+            FILE_SHARE_NONE,
+            Some(&raw const attributes),
+            CREATE_NEW,
+            FILE_ATTRIBUTE_NORMAL,
+            None,
+        )
+    };
+    // SAFETY: the descriptor was allocated by the conversion above and is no longer used.
+    let _ = unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+    // SAFETY: `CreateFileW` returned a new file handle that nothing else owns.
+    Ok(unsafe { File::from_raw_handle(handle?.0) })
+}
+
+/// Copies the current `path` into `dir` as `<stem>-<unix millis>.<ext>`, keeping the newest
+/// [`SNAPSHOT_COUNT`] copies. Does nothing when `path` does not exist yet.
+pub fn snapshot(path: &Path, dir: &Path) -> Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    std::fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+
+    let (stem, extension) = split_name(path);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let target = dir.join(format!("{stem}-{millis}.{extension}"));
+    std::fs::copy(path, &target).wrap_err_with(|| {
+        format!(
+            "Failed to snapshot {} to {}",
+            path.display(),
+            target.display()
+        )
+    })?;
+
+    prune(path, dir, SNAPSHOT_COUNT)?;
+    Ok(Some(target))
+}
+
+/// Removes all but the newest `keep` snapshots of `path` in `dir`.
+pub fn prune(path: &Path, dir: &Path, keep: usize) -> Result<()> {
+    let existing = snapshots(path, dir)?;
+    for old in existing.iter().take(existing.len().saturating_sub(keep)) {
+        std::fs::remove_file(old)
+            .wrap_err_with(|| format!("Failed to remove old snapshot {}", old.display()))?;
+    }
+    Ok(())
+}
+
+/// Snapshots of `path` in `dir`, oldest first.
+pub fn snapshots(path: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let (stem, extension) = split_name(path);
+    let prefix = format!("{stem}-");
+    let suffix = format!(".{extension}");
+
+    let mut found = Vec::new();
+    let entries =
+        std::fs::read_dir(dir).wrap_err_with(|| format!("Failed to list {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(millis) = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+            .and_then(|millis| millis.parse::<u128>().ok())
+        {
+            found.push((millis, entry.path()));
+        }
+    }
+    found.sort();
+    Ok(found.into_iter().map(|(_, path)| path).collect())
+}
+
+fn split_name(path: &Path) -> (String, String) {
+    let stem = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let extension = path.extension().map_or_else(String::new, |extension| {
+        extension.to_string_lossy().into_owned()
+    });
+    (stem, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRITERS: usize = 8;
+    const INCREMENTS: usize = 25;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tb-store-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn locked_read_modify_write_loses_no_updates() {
+        let dir = scratch_dir("counter");
+        let lock_path = dir.join("counter.lock");
+        let path = dir.join("counter.txt");
+        write_atomic(&path, b"0\n").unwrap();
+
+        let writers = (0..WRITERS)
+            .map(|_| {
+                let lock_path = lock_path.clone();
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..INCREMENTS {
+                        let lock = FileLock::acquire(&lock_path).unwrap();
+                        lock.ensure_guards(&lock_path).unwrap();
+                        let contents = std::fs::read_to_string(&path).unwrap();
+                        let count = contents
+                            .strip_suffix('\n')
+                            .and_then(|count| count.parse::<usize>().ok())
+                            .unwrap_or_else(|| panic!("torn counter file: {contents:?}"));
+                        std::thread::yield_now();
+                        write_atomic(&path, format!("{}\n", count + 1).as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let total = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(total, format!("{}\n", WRITERS * INCREMENTS));
+        let leftovers = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "tmp"))
+            .collect::<Vec<_>>();
+        assert!(leftovers.is_empty(), "temporary files left: {leftovers:?}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writes_never_tear() {
+        let dir = scratch_dir("unlocked");
+        let path = dir.join("state.txt");
+        let contents = (0..WRITERS)
+            .map(|writer| format!("{writer}\n").repeat(1000))
+            .collect::<Vec<_>>();
+
+        let writers = contents
+            .iter()
+            .cloned()
+            .map(|contents| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..INCREMENTS {
+                        write_atomic(&path, contents.as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            contents.contains(&written),
+            "torn file of {} bytes",
+            written.len()
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_guards_rejects_another_lock() {
+        let dir = scratch_dir("guards");
+        let lock = FileLock::acquire(&dir.join("a.lock")).unwrap();
+        assert!(lock.ensure_guards(&dir.join("a.lock")).is_ok());
+        assert!(lock.ensure_guards(&dir.join("b.lock")).is_err());
+        drop(lock);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("private");
+        let path = dir.join("secret");
+        write_atomic(&path, b"old").unwrap();
+        write_private(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_keep_the_newest() {
+        let dir = scratch_dir("snapshots");
+        let path = dir.join("config.toml");
+        let snapshot_dir = dir.join("snapshots");
+        for version in 0..=SNAPSHOT_COUNT + 2 {
+            write_atomic(&path, format!("version = {version}\n").as_bytes()).unwrap();
+            snapshot(&path, &snapshot_dir).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let kept = snapshots(&path, &snapshot_dir).unwrap();
+        assert_eq!(kept.len(), SNAPSHOT_COUNT);
+        let newest = std::fs::read_to_string(kept.last().unwrap()).unwrap();
+        assert_eq!(newest, format!("version = {}\n", SNAPSHOT_COUNT + 2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
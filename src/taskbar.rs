@@ -1,12 +1,105 @@
+#[cfg(windows)]
 use eyre::Context;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(windows)]
 use windows::Win32::Foundation::{HWND, LPARAM};
+#[cfg(windows)]
 use windows::Win32::UI::Shell::{
     ABM_GETSTATE, ABM_SETSTATE, ABS_ALWAYSONTOP, ABS_AUTOHIDE, APPBARDATA, SHAppBarMessage,
 };
+#[cfg(windows)]
 use windows::Win32::UI::WindowsAndMessaging::FindWindowW;
+#[cfg(windows)]
 use windows::core::w;
 
+/// Set to use an in-memory taskbar instead of the shell's, e.g. to exercise the tray, IPC and
+/// server modes on a machine without Explorer.
+pub const FAKE_TASKBAR_ENV_VAR: &str = "TB_FAKE_TASKBAR";
+
+/// Reads and changes the taskbar's auto-hide state.
+pub trait Backend: Sync {
+    fn auto_hide(&self) -> eyre::Result<bool>;
+    /// Applies `enabled` and returns the resulting state.
+    fn set_auto_hide(&self, enabled: bool) -> eyre::Result<bool>;
+}
+
+/// The Explorer taskbar, through `SHAppBarMessage`. Other platforms have none, so every call
+/// fails there.
+#[derive(Debug)]
+pub struct Shell;
+
+#[cfg(windows)]
+impl Backend for Shell {
+    fn auto_hide(&self) -> eyre::Result<bool> {
+        shell_auto_hide()
+    }
+
+    fn set_auto_hide(&self, enabled: bool) -> eyre::Result<bool> {
+        shell_set_auto_hide(enabled)
+    }
+}
+
+#[cfg(not(windows))]
+impl Backend for Shell {
+    fn auto_hide(&self) -> eyre::Result<bool> {
+        Err(no_taskbar())
+    }
+
+    fn set_auto_hide(&self, _enabled: bool) -> eyre::Result<bool> {
+        Err(no_taskbar())
+    }
+}
+
+#[cfg(not(windows))]
+fn no_taskbar() -> eyre::Report {
+    eyre::eyre!(
+        "There is no taskbar on this platform; set {FAKE_TASKBAR_ENV_VAR} to use a fake one"
+    )
+}
+
+/// A taskbar that only exists in this process, starting out shown.
+#[derive(Debug, Default)]
+pub struct Fake {
+    auto_hide: AtomicBool,
+}
+
+impl Backend for Fake {
+    fn auto_hide(&self) -> eyre::Result<bool> {
+        Ok(self.auto_hide.load(Ordering::SeqCst))
+    }
+
+    fn set_auto_hide(&self, enabled: bool) -> eyre::Result<bool> {
+        self.auto_hide.store(enabled, Ordering::SeqCst);
+        Ok(enabled)
+    }
+}
+
+static FAKE: Fake = Fake {
+    auto_hide: AtomicBool::new(false),
+};
+
+/// The backend every taskbar change goes through: [`Shell`], or [`Fake`] when
+/// [`FAKE_TASKBAR_ENV_VAR`] is set.
+pub fn backend() -> &'static dyn Backend {
+    static USE_FAKE: OnceLock<bool> = OnceLock::new();
+    if *USE_FAKE.get_or_init(|| std::env::var_os(FAKE_TASKBAR_ENV_VAR).is_some()) {
+        &FAKE
+    } else {
+        &Shell
+    }
+}
+
 pub fn is_taskbar_auto_hide_enabled() -> eyre::Result<bool> {
+    backend().auto_hide()
+}
+
+pub fn set_taskbar_auto_hide(enabled: bool) -> eyre::Result<bool> {
+    backend().set_auto_hide(enabled)
+}
+
+#[cfg(windows)]
+fn shell_auto_hide() -> eyre::Result<bool> {
     let hwnd = find_taskbar_window()?;
     let mut data = APPBARDATA {
         cbSize: std::mem::size_of::<APPBARDATA>() as u32,
@@ -18,7 +111,8 @@ pub fn is_taskbar_auto_hide_enabled() -> eyre::Result<bool> {
     Ok((state & ABS_AUTOHIDE as usize) != 0)
 }
 
-pub fn toggle_taskbar_auto_hide() -> eyre::Result<bool> {
+#[cfg(windows)]
+fn shell_set_auto_hide(enabled: bool) -> eyre::Result<bool> {
     let hwnd = find_taskbar_window()?;
     let mut data = APPBARDATA {
         cbSize: std::mem::size_of::<APPBARDATA>() as u32,
@@ -27,12 +121,11 @@ pub fn toggle_taskbar_auto_hide() -> eyre::Result<bool> {
     };
 
     let current_state = unsafe { SHAppBarMessage(ABM_GETSTATE, &mut data) };
-    let currently_enabled = (current_state & ABS_AUTOHIDE as usize) != 0;
 
-    let mut next_state = if currently_enabled {
-        current_state & !(ABS_AUTOHIDE as usize)
-    } else {
+    let mut next_state = if enabled {
         current_state | ABS_AUTOHIDE as usize
+    } else {
+        current_state & !(ABS_AUTOHIDE as usize)
     };
 
     if (next_state & ABS_ALWAYSONTOP as usize) == 0 {
@@ -45,10 +138,11 @@ pub fn toggle_taskbar_auto_hide() -> eyre::Result<bool> {
         eyre::bail!("Failed to set taskbar state")
     }
 
-    Ok(!currently_enabled)
+    Ok(enabled)
 }
 
-fn find_taskbar_window() -> eyre::Result<HWND> {
+#[cfg(windows)]
+pub fn find_taskbar_window() -> eyre::Result<HWND> {
     let hwnd = unsafe { FindWindowW(w!("Shell_TrayWnd"), None) }
         .wrap_err("Failed to locate Shell_TrayWnd")?;
     if hwnd.0.is_null() {
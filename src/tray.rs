@@ -1,46 +1,98 @@
+use crate::action::{Action, Source};
+use crate::cache;
+use crate::condition::{self, ForegroundWindow};
+use crate::config::{self, Config, LOG_LEVELS};
+use crate::events;
+use crate::history;
 use crate::hotkey::{self, HotkeyRegistration};
+use crate::instance::{self, InstanceGuard};
+use crate::ipc;
+use crate::keymap::{Binding, Trigger};
+use crate::logbuffer;
+use crate::loglevel;
+use crate::mouse::{self, DwellTracker, MouseTrigger, ScreenZone};
+use crate::paths;
+use crate::settings;
 use crate::taskbar;
 use eyre::{Context, ContextCompat, Result, eyre};
 use std::ffi::c_void;
-use std::sync::OnceLock;
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+use std::os::windows::process::CommandExt;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::{
+    CloseHandle, GENERIC_READ, GENERIC_WRITE, HANDLE, HWND, LPARAM, LRESULT, POINT, WPARAM,
+};
+use windows::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, MONITOR_DEFAULTTONEAREST, MONITORINFO, MonitorFromPoint,
+};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
 use windows::Win32::System::Console::{
-    AllocConsole, CTRL_BREAK_EVENT, CTRL_C_EVENT, CTRL_CLOSE_EVENT, FreeConsole,
-    GetConsoleProcessList, SetConsoleCtrlHandler,
+    AllocConsole, CONSOLE_MODE, CTRL_BREAK_EVENT, CTRL_C_EVENT, CTRL_CLOSE_EVENT,
+    ENABLE_VIRTUAL_TERMINAL_PROCESSING, FreeConsole, GetConsoleMode, GetConsoleProcessList,
+    STD_ERROR_HANDLE, STD_OUTPUT_HANDLE, SetConsoleCtrlHandler, SetConsoleMode, SetStdHandle,
 };
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
-use windows::Win32::UI::Input::KeyboardAndMouse::{RegisterHotKey, UnregisterHotKey};
+use windows::Win32::System::Threading::DETACHED_PROCESS;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetDoubleClickTime, HOT_KEY_MODIFIERS, RegisterHotKey, UnregisterHotKey,
+};
 use windows::Win32::UI::Shell::{
-    NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW, Shell_NotifyIconW,
+    NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NOTIFYICONDATAW, NOTIFYICONIDENTIFIER,
+    Shell_NotifyIconGetRect, Shell_NotifyIconW,
 };
 use windows::Win32::UI::WindowsAndMessaging::{
-    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DestroyWindow,
-    DispatchMessageW, EnableMenuItem, GWLP_USERDATA, GetCursorPos, GetMessageW, GetWindowLongPtrW,
-    HICON, IDI_APPLICATION, IDNO, IDYES, LoadIconW, MB_ICONINFORMATION, MB_ICONQUESTION, MB_OK,
-    MB_YESNO, MF_BYCOMMAND, MF_GRAYED, MF_SEPARATOR, MF_STRING, MSG, MessageBoxW, PostMessageW,
-    PostQuitMessage, RegisterClassW, RegisterWindowMessageW, SW_SHOW,
-    SetForegroundWindow, SetWindowLongPtrW, ShowWindow, TPM_LEFTALIGN, TPM_RETURNCMD,
-    TPM_RIGHTBUTTON, TPM_TOPALIGN, TrackPopupMenu, TranslateMessage, WM_CLOSE,
-    WM_CONTEXTMENU, WM_CREATE, WM_DESTROY, WM_HOTKEY, WM_LBUTTONDBLCLK, WM_RBUTTONUP, WM_USER,
-    WNDCLASSW, WS_OVERLAPPEDWINDOW,
+    AllowSetForegroundWindow, AppendMenuW, CallNextHookEx, CreatePopupMenu, CreateWindowExW,
+    DefWindowProcW, DestroyMenu, DestroyWindow, DispatchMessageW, EnableMenuItem, FindWindowExW,
+    FindWindowW, GWLP_USERDATA, GetCursorPos, GetMessageW, GetWindowLongPtrW,
+    GetWindowThreadProcessId, HHOOK, HICON, HMENU, IDI_APPLICATION, IDNO, IDYES, KillTimer,
+    LoadIconW, MB_ICONINFORMATION, MB_ICONQUESTION, MB_OK, MB_YESNO, MF_BYCOMMAND, MF_CHECKED,
+    MF_GRAYED, MF_POPUP, MF_SEPARATOR, MF_STRING, MSG, MSLLHOOKSTRUCT, MessageBoxW, PostMessageW,
+    PostQuitMessage, RegisterClassW, RegisterWindowMessageW, SW_SHOW, SetForegroundWindow,
+    SetTimer, SetWindowLongPtrW, SetWindowsHookExW, ShowWindow, TPM_LEFTALIGN, TPM_RETURNCMD,
+    TPM_RIGHTBUTTON, TPM_TOPALIGN, TrackPopupMenu, TranslateMessage, UnhookWindowsHookEx,
+    WH_MOUSE_LL, WM_CLOSE, WM_CONTEXTMENU, WM_CREATE, WM_DESTROY, WM_HOTKEY, WM_LBUTTONDBLCLK,
+    WM_LBUTTONUP, WM_MBUTTONUP, WM_MOUSEWHEEL, WM_RBUTTONUP, WM_TIMER, WM_USER, WNDCLASSW,
+    WS_OVERLAPPEDWINDOW,
 };
 use windows::core::{BOOL, HSTRING, PCWSTR, w};
 
-const HOTKEY_ID: i32 = 1;
+const FIRST_HOTKEY_ID: i32 = 1;
 const TRAY_ICON_ID: u32 = 1;
 const WM_TRAY_CALLBACK: u32 = WM_USER + 1;
+const WM_TRAY_WHEEL: u32 = WM_USER + 2;
+/// Posted by a second `tb` launch so the running tray opens its menu.
+const WM_TRAY_SHOW_MENU: u32 = WM_USER + 3;
+
+const TIMER_SINGLE_CLICK: usize = 1;
+const TIMER_HOT_CORNERS: usize = 2;
+const TIMER_ENFORCE: usize = 3;
+const HOT_CORNER_POLL_MS: u32 = 50;
+const ENFORCE_POLL_MS: u32 = 2000;
 
 const CMD_TOGGLE: usize = 0x3000;
 const CMD_SHOW_LOGS: usize = 0x3001;
 const CMD_HIDE_LOGS: usize = 0x3002;
 const CMD_ABOUT: usize = 0x3003;
 const CMD_EXIT: usize = 0x3004;
+const CMD_UNDO: usize = 0x3005;
+/// First of the "Log level" submenu entries, one per [`LOG_LEVELS`] entry.
+const CMD_LOG_LEVEL: usize = 0x3100;
+
+/// Changes listed in the "Recent changes" submenu.
+const RECENT_CHANGES_SHOWN: usize = 10;
 
+const TRAY_WINDOW_CLASS: PCWSTR = w!("tb_tray_window");
 static TRAY_VERSION: OnceLock<&'static str> = OnceLock::new();
-static TRAY_HOTKEY: OnceLock<HotkeyRegistration> = OnceLock::new();
-static TRAY_HOTKEY_EXPRESSION: OnceLock<String> = OnceLock::new();
+static TRAY_BINDINGS: OnceLock<Vec<Binding>> = OnceLock::new();
+static TRAY_CONFIG: OnceLock<Config> = OnceLock::new();
+static INITIAL_AUTO_HIDE: OnceLock<bool> = OnceLock::new();
+/// Last auto-hide state tb applied, which `policies.enforce` keeps in place.
+static LAST_APPLIED_AUTO_HIDE: Mutex<Option<bool>> = Mutex::new(None);
 static WM_TASKBAR_CREATED: OnceLock<u32> = OnceLock::new();
 static TRAY_HWND: OnceLock<isize> = OnceLock::new();
+static TRAY_MOUSE_HOOK: OnceLock<isize> = OnceLock::new();
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum ConsoleMode {
@@ -53,6 +105,10 @@ struct TrayState {
     version: &'static str,
     hotkey_expression: String,
     console_mode: ConsoleMode,
+    /// `CONOUT$` of the console opened by "Show logs", closed again by "Hide logs".
+    console_output: Option<HANDLE>,
+    dwell: DwellTracker,
+    suppress_next_click: bool,
 }
 
 impl TrayState {
@@ -61,6 +117,9 @@ impl TrayState {
             version,
             hotkey_expression,
             console_mode: ConsoleMode::Detached,
+            console_output: None,
+            dwell: DwellTracker::default(),
+            suppress_next_click: false,
         }
     }
 
@@ -77,18 +136,32 @@ impl TrayState {
             return;
         }
 
-        let _ = unsafe { AllocConsole() };
+        let allocated = unsafe { AllocConsole() }.is_ok();
         let console = unsafe { windows::Win32::System::Console::GetConsoleWindow() };
         if !console.0.is_null() {
             let _ = unsafe { ShowWindow(console, SW_SHOW) };
         }
         self.console_mode = ConsoleMode::Owned;
+        if !allocated {
+            return;
+        }
+
+        match attach_console_output() {
+            Ok(handle) => self.console_output = Some(handle),
+            Err(error) => tracing::warn!("Failed to attach log output to the console: {error}"),
+        }
+        if let Err(error) = logbuffer::replay() {
+            tracing::warn!("Failed to replay earlier log output: {error}");
+        }
     }
 
     fn hide_logs(&mut self) {
         if !self.can_hide_logs() {
             return;
         }
+        if let Some(handle) = self.console_output.take() {
+            let _ = unsafe { CloseHandle(handle) };
+        }
         let _ = unsafe { FreeConsole() };
         self.console_mode = ConsoleMode::Detached;
     }
@@ -101,7 +174,24 @@ impl TrayState {
     }
 }
 
-pub fn run_tray(version: &'static str) -> Result<()> {
+/// Runs the tray until it exits. When another tray is already running, opens its menu and
+/// returns, or with `replace` asks it to quit and takes over once it has.
+pub fn run_tray(version: &'static str, replace: bool) -> Result<()> {
+    let _instance = match InstanceGuard::try_acquire()? {
+        Some(instance) => instance,
+        None if replace => {
+            request_tray_exit()?;
+            tracing::info!("Asked the running tray to exit");
+            InstanceGuard::wait(instance::REPLACE_TIMEOUT)?
+        }
+        None => {
+            show_running_tray_menu()?;
+            println!("tb is already running; opened its menu. Use --replace to restart it.");
+            return Ok(());
+        }
+    };
+
+    let started = events::now_millis();
     let inherited_console = is_inheriting_console();
     if inherited_console {
         attach_ctrl_c_handler()?;
@@ -109,22 +199,128 @@ pub fn run_tray(version: &'static str) -> Result<()> {
         detach_default_console_if_not_inherited();
     }
 
-    let hotkey = hotkey::load_hotkey()?;
+    let config = config::load_config()?;
+    let bindings = config.keymap()?;
     let _ = TRAY_VERSION.set(version);
-    let _ = TRAY_HOTKEY.set(hotkey.registration);
-    let _ = TRAY_HOTKEY_EXPRESSION.set(hotkey.expression);
+    let _ = TRAY_BINDINGS.set(bindings);
+    let _ = TRAY_CONFIG.set(config);
     let taskbar_created = unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) };
     let _ = WM_TASKBAR_CREATED.set(taskbar_created);
 
     let hwnd = create_window()?;
     let _ = TRAY_HWND.set(hwnd.0 as isize);
-    unsafe { register_hotkey(hwnd)? };
+    unsafe { register_hotkeys(hwnd)? };
+    start_mouse_triggers(hwnd)?;
     add_tray_icon(hwnd)?;
+    apply_startup_config(hwnd);
+    // CLI commands fall back to acting on the taskbar themselves, so this is not fatal.
+    if let Err(error) = ipc::serve(remember_auto_hide, tray_info(version, started)) {
+        tracing::warn!("{error:#}");
+    }
 
     run_message_loop()?;
     Ok(())
 }
 
+fn apply_startup_config(hwnd: HWND) {
+    let Some(config) = TRAY_CONFIG.get() else {
+        return;
+    };
+
+    match taskbar::is_taskbar_auto_hide_enabled() {
+        Ok(enabled) => {
+            let _ = INITIAL_AUTO_HIDE.set(enabled);
+            remember_auto_hide(enabled);
+        }
+        Err(error) => tracing::warn!("Failed to read initial taskbar state: {error}"),
+    }
+
+    if let Some(auto_hide) = config.startup.auto_hide {
+        handle_action(Action::for_state(auto_hide), Source::Startup);
+    }
+    if config.startup.show_logs {
+        with_state(hwnd, TrayState::show_logs);
+    }
+    if config.policies.enforce {
+        unsafe { SetTimer(Some(hwnd), TIMER_ENFORCE, ENFORCE_POLL_MS, None) };
+    }
+
+    match cache::enforce_retention(config.cache.retention_days) {
+        Ok(report) if report.files > 0 => {
+            tracing::info!("Removed {} expired cache file(s)", report.files);
+        }
+        Ok(_) => {}
+        Err(error) => tracing::warn!("Failed to apply cache retention: {error}"),
+    }
+}
+
+fn remember_auto_hide(enabled: bool) {
+    *LAST_APPLIED_AUTO_HIDE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(enabled);
+}
+
+fn enforce_auto_hide() {
+    let Some(desired) = *LAST_APPLIED_AUTO_HIDE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+    else {
+        return;
+    };
+
+    match taskbar::is_taskbar_auto_hide_enabled() {
+        Ok(current) if current != desired => {
+            tracing::info!(
+                "Taskbar auto-hide was changed outside tb, re-applying {}",
+                if desired { "enabled" } else { "disabled" }
+            );
+            if let Err(error) = Action::for_state(desired).perform(Source::Rule) {
+                tracing::error!("Failed to enforce taskbar auto-hide: {error}");
+            }
+        }
+        Ok(_) => {}
+        Err(error) => tracing::debug!("Failed to read taskbar state: {error}"),
+    }
+}
+
+fn restore_initial_auto_hide() {
+    let restore = TRAY_CONFIG
+        .get()
+        .is_some_and(|config| config.policies.restore_on_exit);
+    if let Some(initial) = INITIAL_AUTO_HIDE.get().copied()
+        && restore
+        && let Err(error) = Action::for_state(initial).perform(Source::Rule)
+    {
+        tracing::error!("Failed to restore taskbar auto-hide on exit: {error}");
+    }
+}
+
+/// Points stdout and stderr at a freshly allocated console. The standard handles still refer to
+/// the console detached at startup, so output would otherwise never reach the new window.
+fn attach_console_output() -> Result<HANDLE> {
+    let output = unsafe {
+        CreateFileW(
+            w!("CONOUT$"),
+            (GENERIC_READ | GENERIC_WRITE).0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAGS_AND_ATTRIBUTES(0),
+            None,
+        )
+    }
+    .wrap_err("Failed to open CONOUT$")?;
+    unsafe { SetStdHandle(STD_OUTPUT_HANDLE, output) }.wrap_err("Failed to set stdout")?;
+    unsafe { SetStdHandle(STD_ERROR_HANDLE, output) }.wrap_err("Failed to set stderr")?;
+
+    // Log lines carry ANSI colours.
+    let mut mode = CONSOLE_MODE::default();
+    if unsafe { GetConsoleMode(output, &raw mut mode) }.is_ok() {
+        let _ = unsafe { SetConsoleMode(output, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) };
+    }
+    Ok(output)
+}
+
 fn detach_default_console_if_not_inherited() {
     let console = unsafe { windows::Win32::System::Console::GetConsoleWindow() };
     if console.0.is_null() {
@@ -170,7 +366,7 @@ unsafe extern "system" fn ctrl_c_handler(ctrl_type: u32) -> BOOL {
 
 fn create_window() -> Result<HWND> {
     let hinstance = unsafe { GetModuleHandleW(None) }.wrap_err("GetModuleHandleW failed")?;
-    let class_name = w!("tb_tray_window");
+    let class_name = TRAY_WINDOW_CLASS;
 
     let wnd_class = WNDCLASSW {
         lpfnWndProc: Some(window_proc),
@@ -214,27 +410,279 @@ fn run_message_loop() -> Result<()> {
     Ok(())
 }
 
-unsafe fn register_hotkey(hwnd: HWND) -> Result<()> {
-    let registration = TRAY_HOTKEY
+unsafe fn register_hotkeys(hwnd: HWND) -> Result<()> {
+    let bindings = TRAY_BINDINGS
         .get()
-        .copied()
-        .ok_or_else(|| eyre!("Tray hotkey not configured"))?;
+        .ok_or_else(|| eyre!("Tray hotkeys not configured"))?;
 
-    unsafe {
-        RegisterHotKey(
-            Some(hwnd),
-            HOTKEY_ID,
-            registration.modifiers,
-            registration.vk,
+    // Several bindings may share a hotkey with different conditions; register it once under
+    // the id of its first binding.
+    for (index, (id, binding)) in (FIRST_HOTKEY_ID..).zip(bindings).enumerate() {
+        let Some(hotkey) = binding.trigger.hotkey() else {
+            continue;
+        };
+        if bindings[..index]
+            .iter()
+            .any(|earlier| same_hotkey(earlier, &hotkey.expression))
+        {
+            continue;
+        }
+
+        let registration = hotkey.registration;
+        unsafe {
+            RegisterHotKey(
+                Some(hwnd),
+                id,
+                HOT_KEY_MODIFIERS(registration.modifiers),
+                registration.vk,
+            )
+        }
+        .ok()
+        .wrap_err_with(|| format!("Failed to register global hotkey {}", hotkey.expression))?;
+    }
+    Ok(())
+}
+
+fn same_hotkey(binding: &Binding, expression: &str) -> bool {
+    binding
+        .trigger
+        .hotkey()
+        .is_some_and(|hotkey| hotkey.expression == expression)
+}
+
+unsafe fn unregister_hotkeys(hwnd: HWND) {
+    let count = TRAY_BINDINGS.get().map_or(0, Vec::len);
+    for id in (FIRST_HOTKEY_ID..).take(count) {
+        let _ = unsafe { UnregisterHotKey(Some(hwnd), id) };
+    }
+}
+
+fn binding_for_hotkey_id(id: usize) -> Option<&'static Binding> {
+    let index = id.checked_sub(usize::try_from(FIRST_HOTKEY_ID).ok()?)?;
+    TRAY_BINDINGS.get()?.get(index)
+}
+
+/// Runs the first binding for a hotkey whose condition matches the foreground window, or
+/// replays the keystroke to that window when none does.
+fn handle_hotkey(hwnd: HWND, id: usize) {
+    let Some(hotkey) = binding_for_hotkey_id(id).and_then(|binding| binding.trigger.hotkey())
+    else {
+        return;
+    };
+    let candidates = TRAY_BINDINGS
+        .get()
+        .into_iter()
+        .flatten()
+        .filter(|binding| same_hotkey(binding, &hotkey.expression));
+
+    let mut window = None;
+    for binding in candidates {
+        if condition_allows(binding, &mut window) {
+            handle_action(binding.action, Source::Hotkey);
+            return;
+        }
+    }
+
+    tracing::debug!(
+        "No binding for {} matched the foreground window, passing it through",
+        hotkey.expression
+    );
+    if let Ok(id) = i32::try_from(id) {
+        pass_through_hotkey(hwnd, id, hotkey.registration);
+    }
+}
+
+/// Evaluates a binding's condition, looking the foreground window up at most once per event.
+///
+/// When the window cannot be inspected no condition matches, not even an `unless`, so the
+/// event passes through instead of firing on a guess.
+fn condition_allows(binding: &Binding, window: &mut Option<Option<ForegroundWindow>>) -> bool {
+    let Some(condition) = &binding.condition else {
+        return true;
+    };
+    let window = window.get_or_insert_with(|| {
+        condition::foreground_window()
+            .inspect_err(|error| tracing::debug!("Failed to inspect foreground window: {error}"))
+            .ok()
+    });
+    window
+        .as_ref()
+        .is_some_and(|window| condition.matches(window))
+}
+
+fn pass_through_hotkey(hwnd: HWND, id: i32, registration: HotkeyRegistration) {
+    let _ = unsafe { UnregisterHotKey(Some(hwnd), id) };
+    if let Err(error) = hotkey::send_keystroke(registration) {
+        tracing::error!("Failed to pass hotkey through: {error}");
+    }
+    let modifiers = HOT_KEY_MODIFIERS(registration.modifiers);
+    if let Err(error) = unsafe { RegisterHotKey(Some(hwnd), id, modifiers, registration.vk) } {
+        tracing::error!("Failed to re-register hotkey after passing it through: {error}");
+    }
+}
+
+fn mouse_bindings() -> impl Iterator<Item = (MouseTrigger, &'static Binding)> {
+    TRAY_BINDINGS
+        .get()
+        .into_iter()
+        .flatten()
+        .filter_map(|binding| match binding.trigger {
+            Trigger::Mouse(trigger) => Some((trigger, binding)),
+            Trigger::Hotkey(_) => None,
+        })
+}
+
+fn mouse_triggers() -> impl Iterator<Item = MouseTrigger> {
+    mouse_bindings().map(|(trigger, _)| trigger)
+}
+
+fn mouse_action(trigger: MouseTrigger) -> Option<Action> {
+    let mut window = None;
+    mouse_bindings()
+        .filter(|(bound, _)| *bound == trigger)
+        .find(|(_, binding)| condition_allows(binding, &mut window))
+        .map(|(_, binding)| binding.action)
+}
+
+fn zone_dwell(zone: ScreenZone) -> Option<Duration> {
+    mouse_triggers().find_map(|trigger| match trigger {
+        MouseTrigger::Zone { zone: bound, dwell } if bound == zone => Some(dwell),
+        _ => None,
+    })
+}
+
+fn start_mouse_triggers(hwnd: HWND) -> Result<()> {
+    if mouse_triggers().any(|trigger| matches!(trigger, MouseTrigger::Zone { .. })) {
+        unsafe { SetTimer(Some(hwnd), TIMER_HOT_CORNERS, HOT_CORNER_POLL_MS, None) };
+    }
+
+    let wants_wheel = mouse_triggers().any(|trigger| {
+        matches!(
+            trigger,
+            MouseTrigger::TrayWheelUp | MouseTrigger::TrayWheelDown
         )
+    });
+    if wants_wheel {
+        // Wheel events are not delivered to notification icons, so watch them globally and
+        // forward the ones that land on our icon.
+        let module = unsafe { GetModuleHandleW(None) }.wrap_err("GetModuleHandleW failed")?;
+        let hook = unsafe {
+            SetWindowsHookExW(WH_MOUSE_LL, Some(wheel_hook_proc), Some(module.into()), 0)
+        }
+        .wrap_err("Failed to install mouse wheel hook")?;
+        let _ = TRAY_MOUSE_HOOK.set(hook.0 as isize);
     }
-        .ok()
-        .wrap_err("Failed to register global hotkey")?;
     Ok(())
 }
 
-unsafe fn unregister_hotkey(hwnd: HWND) {
-    let _ = unsafe { UnregisterHotKey(Some(hwnd), HOTKEY_ID) };
+fn stop_mouse_triggers(hwnd: HWND) {
+    let _ = unsafe { KillTimer(Some(hwnd), TIMER_HOT_CORNERS) };
+    let _ = unsafe { KillTimer(Some(hwnd), TIMER_SINGLE_CLICK) };
+    if let Some(hook_bits) = TRAY_MOUSE_HOOK.get().copied() {
+        let _ = unsafe { UnhookWindowsHookEx(HHOOK(hook_bits as *mut c_void)) };
+    }
+}
+
+unsafe extern "system" fn wheel_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0
+        && wparam.0 as u32 == WM_MOUSEWHEEL
+        && let Some(hwnd_bits) = TRAY_HWND.get().copied()
+    {
+        let hwnd = HWND(hwnd_bits as *mut c_void);
+        let info = unsafe { &*(lparam.0 as *const MSLLHOOKSTRUCT) };
+        if is_over_tray_icon(hwnd, info.pt) {
+            let delta = (info.mouseData >> 16) as u16 as i16;
+            let _ = unsafe {
+                PostMessageW(
+                    Some(hwnd),
+                    WM_TRAY_WHEEL,
+                    WPARAM(usize::from(delta > 0)),
+                    LPARAM(0),
+                )
+            };
+        }
+    }
+    unsafe { CallNextHookEx(None, code, wparam, lparam) }
+}
+
+fn is_over_tray_icon(hwnd: HWND, point: POINT) -> bool {
+    let identifier = NOTIFYICONIDENTIFIER {
+        cbSize: std::mem::size_of::<NOTIFYICONIDENTIFIER>() as u32,
+        hWnd: hwnd,
+        uID: TRAY_ICON_ID,
+        ..Default::default()
+    };
+    match unsafe { Shell_NotifyIconGetRect(&identifier) } {
+        Ok(rect) => {
+            point.x >= rect.left
+                && point.x < rect.right
+                && point.y >= rect.top
+                && point.y < rect.bottom
+        }
+        Err(_) => false,
+    }
+}
+
+fn poll_hot_corners(state: &mut TrayState) {
+    let mut cursor = POINT::default();
+    if unsafe { GetCursorPos(&raw mut cursor) }.is_err() {
+        return;
+    }
+
+    let zone =
+        monitor_rect_at(cursor).and_then(|screen| mouse::zone_at(cursor.x, cursor.y, screen));
+    let fired = state.dwell.update(zone, Instant::now(), zone_dwell);
+    if let Some(zone) = fired {
+        let dwell = zone_dwell(zone).unwrap_or(mouse::DEFAULT_DWELL);
+        handle_mouse_trigger(MouseTrigger::Zone { zone, dwell });
+    }
+}
+
+fn monitor_rect_at(point: POINT) -> Option<mouse::Rect> {
+    let monitor = unsafe { MonitorFromPoint(point, MONITOR_DEFAULTTONEAREST) };
+    let mut info = MONITORINFO {
+        cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    if !unsafe { GetMonitorInfoW(monitor, &raw mut info) }.as_bool() {
+        return None;
+    }
+
+    let rect = info.rcMonitor;
+    Some(mouse::Rect {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    })
+}
+
+/// Defers single clicks by the double-click time so a double click does not also run them.
+fn handle_tray_click(hwnd: HWND) {
+    with_state(hwnd, |state| {
+        if std::mem::take(&mut state.suppress_next_click) {
+            return;
+        }
+        if mouse_action(MouseTrigger::TrayClick).is_some() {
+            unsafe { SetTimer(Some(hwnd), TIMER_SINGLE_CLICK, GetDoubleClickTime(), None) };
+        }
+    });
+}
+
+fn handle_tray_double_click(hwnd: HWND) {
+    let _ = unsafe { KillTimer(Some(hwnd), TIMER_SINGLE_CLICK) };
+    // The second button release of a double click arrives after WM_LBUTTONDBLCLK.
+    with_state(hwnd, |state| state.suppress_next_click = true);
+    handle_action(
+        mouse_action(MouseTrigger::TrayDoubleClick).unwrap_or(Action::Toggle),
+        Source::Mouse,
+    );
+}
+
+fn handle_mouse_trigger(trigger: MouseTrigger) {
+    if let Some(action) = mouse_action(trigger) {
+        handle_action(action, Source::Mouse);
+    }
 }
 
 fn add_tray_icon(hwnd: HWND) -> Result<()> {
@@ -308,6 +756,8 @@ fn show_context_menu(hwnd: HWND) {
         unsafe { AppendMenuW(menu, MF_STRING, CMD_TOGGLE, w!("Toggle taskbar auto-hide")) }.ok();
         unsafe { AppendMenuW(menu, MF_STRING, CMD_SHOW_LOGS, w!("Show logs")) }.ok();
         unsafe { AppendMenuW(menu, MF_STRING, CMD_HIDE_LOGS, w!("Hide logs")) }.ok();
+        append_recent_changes_menu(menu);
+        append_log_level_menu(menu);
         unsafe { AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null()) }.ok();
         unsafe { AppendMenuW(menu, MF_STRING, CMD_ABOUT, w!("About")) }.ok();
         unsafe { AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null()) }.ok();
@@ -351,18 +801,164 @@ fn show_context_menu(hwnd: HWND) {
         unsafe { DestroyMenu(menu) }.ok();
 
         match usize::try_from(selection).unwrap_or_default() {
-            CMD_TOGGLE => handle_toggle(),
+            CMD_TOGGLE => handle_action(Action::Toggle, Source::Menu),
             CMD_SHOW_LOGS => state.show_logs(),
             CMD_HIDE_LOGS => state.hide_logs(),
+            CMD_UNDO => undo_last_change(),
             CMD_ABOUT => show_about_dialog(hwnd, state),
             CMD_EXIT => {
                 unsafe { PostMessageW(Some(hwnd), WM_CLOSE, WPARAM(0), LPARAM(0)) }.ok();
             }
+            command if (CMD_LOG_LEVEL..CMD_LOG_LEVEL + LOG_LEVELS.len()).contains(&command) => {
+                if let Err(error) = loglevel::set_filter(LOG_LEVELS[command - CMD_LOG_LEVEL]) {
+                    tracing::error!("{error}");
+                }
+            }
             _ => {}
         }
     });
 }
 
+/// Adds the "Recent changes" submenu: the latest taskbar changes and "Undo last change".
+fn append_recent_changes_menu(menu: HMENU) {
+    let submenu = match unsafe { CreatePopupMenu() } {
+        Ok(submenu) => submenu,
+        Err(error) => {
+            tracing::error!("Failed to create recent changes menu: {error}");
+            return;
+        }
+    };
+
+    let history = history::list().unwrap_or_else(|error| {
+        tracing::warn!("Failed to read the change history: {error:#}");
+        Vec::new()
+    });
+    if history.is_empty() {
+        unsafe { AppendMenuW(submenu, MF_STRING | MF_GRAYED, 0, w!("No changes yet")) }.ok();
+    }
+    for change in history.iter().take(RECENT_CHANGES_SHOWN) {
+        let label = HSTRING::from(change.to_string());
+        unsafe { AppendMenuW(submenu, MF_STRING | MF_GRAYED, 0, &label) }.ok();
+    }
+    unsafe { AppendMenuW(submenu, MF_SEPARATOR, 0, PCWSTR::null()) }.ok();
+    let undo_flags = if history.is_empty() {
+        MF_STRING | MF_GRAYED
+    } else {
+        MF_STRING
+    };
+    unsafe { AppendMenuW(submenu, undo_flags, CMD_UNDO, w!("Undo last change")) }.ok();
+    unsafe { AppendMenuW(menu, MF_POPUP, submenu.0 as usize, w!("Recent changes")) }.ok();
+}
+
+fn undo_last_change() {
+    match history::undo() {
+        Ok(Some((_, enabled))) => remember_auto_hide(enabled),
+        Ok(None) => tracing::info!("Nothing to undo"),
+        Err(error) => tracing::error!("Failed to undo the last change: {error:#}"),
+    }
+}
+
+/// Adds the "Log level" submenu, checking the active level when the filter is a bare level.
+fn append_log_level_menu(menu: HMENU) {
+    let submenu = match unsafe { CreatePopupMenu() } {
+        Ok(submenu) => submenu,
+        Err(error) => {
+            tracing::error!("Failed to create log level menu: {error}");
+            return;
+        }
+    };
+
+    let current = loglevel::current();
+    for (index, level) in LOG_LEVELS.iter().enumerate() {
+        let flags = if current.eq_ignore_ascii_case(level) {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        let label = HSTRING::from(*level);
+        unsafe { AppendMenuW(submenu, flags, CMD_LOG_LEVEL + index, &label) }.ok();
+    }
+    unsafe { AppendMenuW(menu, MF_POPUP, submenu.0 as usize, w!("Log level")) }.ok();
+}
+
+fn tray_info(version: &str, started: u64) -> ipc::TrayInfo {
+    ipc::TrayInfo {
+        pid: std::process::id(),
+        version: version.to_string(),
+        started,
+        bindings: TRAY_BINDINGS
+            .get()
+            .map(|bindings| bindings.iter().map(ToString::to_string).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Asks the running tray to exit and waits until it has. Returns `false` when none is running.
+///
+/// The tray goes through the same `WM_CLOSE`/`WM_DESTROY` cleanup as its "Exit" menu item, which
+/// removes the icon and unregisters its hotkeys.
+pub fn stop_running_tray() -> Result<bool> {
+    if InstanceGuard::try_acquire()?.is_some() {
+        return Ok(false);
+    }
+    request_tray_exit()?;
+    drop(InstanceGuard::wait(instance::REPLACE_TIMEOUT)?);
+    Ok(true)
+}
+
+/// Starts a new tray in the background, detached from this console, and returns its PID.
+pub fn spawn_tray() -> Result<u32> {
+    let exe = std::env::current_exe().wrap_err("Failed to locate tb.exe")?;
+    let mut command = std::process::Command::new(&exe);
+    if paths::portable_root().is_some() {
+        command.arg("--portable");
+    }
+    for cli in settings::cli_overrides() {
+        command.args(cli.to_args());
+    }
+    let child = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .creation_flags(DETACHED_PROCESS.0)
+        .spawn()
+        .wrap_err_with(|| format!("Failed to start {}", exe.display()))?;
+    Ok(child.id())
+}
+
+/// Asks the running tray to close the same way its "Exit" menu item does.
+fn request_tray_exit() -> Result<()> {
+    let hwnd = unsafe { FindWindowW(TRAY_WINDOW_CLASS, None) }
+        .map_err(|_| eyre!("No running tb tray was found"))?;
+    unsafe { PostMessageW(Some(hwnd), WM_CLOSE, WPARAM(0), LPARAM(0)) }
+        .wrap_err("Failed to ask the running tray to exit")
+}
+
+fn show_running_tray_menu() -> Result<()> {
+    let hwnd = unsafe { FindWindowW(TRAY_WINDOW_CLASS, None) }
+        .map_err(|_| eyre!("Another tray holds the instance lock but has no window yet"))?;
+    // Let the tray take the foreground from us, or its menu will not close when clicked away.
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&raw mut process_id)) };
+    let _ = unsafe { AllowSetForegroundWindow(process_id) };
+    unsafe { PostMessageW(Some(hwnd), WM_TRAY_SHOW_MENU, WPARAM(0), LPARAM(0)) }
+        .wrap_err("Failed to reach the running tray")
+}
+
+/// How many tb trays are running in this session.
+pub fn running_tray_count() -> usize {
+    let mut count = 0;
+    let mut previous = None;
+    while let Ok(hwnd) = unsafe { FindWindowExW(None, previous, TRAY_WINDOW_CLASS, None) } {
+        if hwnd.0.is_null() {
+            break;
+        }
+        count += 1;
+        previous = Some(hwnd);
+    }
+    count
+}
+
 fn show_about_dialog(hwnd: HWND, state: &TrayState) {
     let text = state.about_text();
     let response = unsafe {
@@ -420,15 +1016,10 @@ fn drop_state(hwnd: HWND) {
     }
 }
 
-fn handle_toggle() {
-    match taskbar::toggle_taskbar_auto_hide() {
-        Ok(enabled) => {
-            tracing::info!(
-                "Taskbar auto-hide {}",
-                if enabled { "enabled" } else { "disabled" }
-            );
-        }
-        Err(error) => tracing::error!("Failed to toggle taskbar: {error}"),
+fn handle_action(action: Action, source: Source) {
+    match action.perform(source) {
+        Ok(enabled) => remember_auto_hide(enabled),
+        Err(error) => tracing::error!("Failed to {} taskbar auto-hide: {error}", action.name()),
     }
 }
 
@@ -447,10 +1038,16 @@ pub unsafe extern "system" fn window_proc(
 
             match version {
                 Ok(version) => {
-                    let hotkey_expression = TRAY_HOTKEY_EXPRESSION
+                    let hotkey_expression = TRAY_BINDINGS
                         .get()
-                        .cloned()
-                        .unwrap_or_else(|| "Ctrl+Shift+B".to_string());
+                        .map(|bindings| {
+                            bindings
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        })
+                        .unwrap_or_default();
                     store_state(hwnd, Box::new(TrayState::new(version, hotkey_expression)));
                     LRESULT(0)
                 }
@@ -461,15 +1058,39 @@ pub unsafe extern "system" fn window_proc(
             }
         }
         WM_HOTKEY => {
-            if i32::try_from(wparam.0).ok() == Some(HOTKEY_ID) {
-                handle_toggle();
-            }
+            handle_hotkey(hwnd, wparam.0);
             LRESULT(0)
         }
         WM_TRAY_CALLBACK => {
             match lparam.0 as u32 {
                 WM_RBUTTONUP | WM_CONTEXTMENU => show_context_menu(hwnd),
-                WM_LBUTTONDBLCLK => handle_toggle(),
+                WM_LBUTTONUP => handle_tray_click(hwnd),
+                WM_LBUTTONDBLCLK => handle_tray_double_click(hwnd),
+                WM_MBUTTONUP => handle_mouse_trigger(MouseTrigger::TrayMiddleClick),
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_TRAY_SHOW_MENU => {
+            show_context_menu(hwnd);
+            LRESULT(0)
+        }
+        WM_TRAY_WHEEL => {
+            handle_mouse_trigger(if wparam.0 == 1 {
+                MouseTrigger::TrayWheelUp
+            } else {
+                MouseTrigger::TrayWheelDown
+            });
+            LRESULT(0)
+        }
+        WM_TIMER => {
+            match wparam.0 {
+                TIMER_SINGLE_CLICK => {
+                    let _ = unsafe { KillTimer(Some(hwnd), TIMER_SINGLE_CLICK) };
+                    handle_mouse_trigger(MouseTrigger::TrayClick);
+                }
+                TIMER_HOT_CORNERS => with_state(hwnd, poll_hot_corners),
+                TIMER_ENFORCE => enforce_auto_hide(),
                 _ => {}
             }
             LRESULT(0)
@@ -479,7 +1100,10 @@ pub unsafe extern "system" fn window_proc(
             LRESULT(0)
         }
         WM_DESTROY => {
-            unsafe { unregister_hotkey(hwnd) };
+            unsafe { unregister_hotkeys(hwnd) };
+            stop_mouse_triggers(hwnd);
+            let _ = unsafe { KillTimer(Some(hwnd), TIMER_ENFORCE) };
+            restore_initial_auto_hide();
             if let Err(error) = delete_tray_icon(hwnd) {
                 tracing::error!("Failed to delete tray icon: {error}");
             }
@@ -0,0 +1,114 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tb::http;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the REST API in this process against the fake taskbar and exercises every route.
+#[test]
+fn serve_answers_requests() {
+    let home = std::env::temp_dir().join(format!("tb-http-{}", std::process::id()));
+    // SAFETY: this is the only test in the binary and the server has not started yet.
+    unsafe {
+        std::env::set_var("TB_FAKE_TASKBAR", "1");
+        std::env::set_var("TB_HOME_DIR", &home);
+        std::env::set_var("TB_CACHE_DIR", &home);
+        // No tray answers on the control endpoint of a made-up user.
+        std::env::set_var("USERNAME", format!("http-test-{}", std::process::id()));
+    }
+    std::thread::spawn(|| http::serve(address()));
+    let token = wait_for_server();
+
+    let (status, body) = request("GET", "/state", None, "");
+    assert_eq!(status, 401, "{body}");
+
+    let (status, body) = request("GET", "/state", Some(&token), "");
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body, r#"{"autoHide":false}"#);
+
+    let (status, body) = request("POST", "/state", Some(&token), "not json");
+    assert_eq!(status, 400, "{body}");
+    let (status, body) = request("POST", "/state", Some(&token), "{}");
+    assert_eq!(status, 400, "{body}");
+
+    let mut events = connect();
+    write!(
+        events,
+        "GET /events?token={token} HTTP/1.1\r\nHost: {}\r\n\r\n",
+        address()
+    )
+    .unwrap();
+    let mut events = BufReader::new(events);
+    assert_eq!(next_event(&mut events), r#"{"autoHide":false}"#);
+
+    let (status, body) = request("POST", "/toggle", Some(&token), "");
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(body, r#"{"autoHide":true}"#);
+    assert_eq!(next_event(&mut events), r#"{"autoHide":true}"#);
+
+    let _ = std::fs::remove_dir_all(&home);
+}
+
+/// A loopback address with a port nothing else was using when the test started.
+fn address() -> &'static str {
+    static ADDRESS: OnceLock<String> = OnceLock::new();
+    ADDRESS.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    })
+}
+
+/// Waits until the server has written its token and accepts connections, then returns the token.
+fn wait_for_server() -> String {
+    let started = Instant::now();
+    loop {
+        if let Ok(token) = std::fs::read_to_string(http::token_path().unwrap())
+            && TcpStream::connect(address()).is_ok()
+        {
+            return token.trim().to_string();
+        }
+        assert!(started.elapsed() < TIMEOUT, "the server did not start");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn connect() -> TcpStream {
+    let stream = TcpStream::connect(address()).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream
+}
+
+/// Sends one request and returns the status code and body.
+fn request(method: &str, path: &str, token: Option<&str>, body: &str) -> (u16, String) {
+    let mut stream = connect();
+    let authorization = token
+        .map(|token| format!("Authorization: Bearer {token}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: {}\r\n{authorization}Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        address(),
+        body.len()
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+/// Reads the event stream up to the next `data:` line and returns its payload.
+fn next_event(events: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        assert!(events.read_line(&mut line).unwrap() > 0, "the stream ended");
+        if let Some(data) = line.strip_prefix("data: ") {
+            return data.trim().to_string();
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use tb::ipc::{self, PROTOCOL_VERSION, Request, TrayInfo};
+
+/// Serves the control endpoint in this process against the fake taskbar and talks to it the way
+/// `tb toggle` and `tb tray-status` do.
+#[test]
+fn serve_answers_requests() {
+    let home = std::env::temp_dir().join(format!("tb-ipc-{}", std::process::id()));
+    // SAFETY: this is the only test in the binary, so no other thread reads the environment.
+    unsafe {
+        std::env::set_var("TB_FAKE_TASKBAR", "1");
+        std::env::set_var("TB_HOME_DIR", &home);
+        std::env::set_var("TB_CACHE_DIR", &home);
+        // The endpoint is named after the user; a unique name keeps a real tray out of the way.
+        std::env::set_var("USERNAME", format!("ipc-test-{}", std::process::id()));
+    }
+
+    ipc::serve(
+        |_| {},
+        TrayInfo {
+            pid: std::process::id(),
+            version: "test".to_string(),
+            started: 1,
+            bindings: vec!["Ctrl+Shift+B -> toggle".to_string()],
+        },
+    )
+    .unwrap();
+
+    let status = ipc::send(&Request::new("status")).unwrap().unwrap();
+    assert!(status.ok, "{:?}", status.error);
+    assert_eq!(status.auto_hide, Some(false));
+
+    let toggle = ipc::send(&Request::new("toggle")).unwrap().unwrap();
+    assert!(toggle.ok, "{:?}", toggle.error);
+    assert_eq!(toggle.auto_hide, Some(true));
+
+    let info = ipc::send(&Request::new("info")).unwrap().unwrap();
+    assert!(info.ok, "{:?}", info.error);
+    let tray = info.tray.unwrap();
+    assert_eq!(tray.version, "test");
+    assert_eq!(tray.bindings, ["Ctrl+Shift+B -> toggle"]);
+
+    let outdated = Request {
+        version: PROTOCOL_VERSION + 1,
+        ..Request::new("status")
+    };
+    let refused = ipc::send(&outdated).unwrap().unwrap();
+    assert!(!refused.ok);
+    assert_eq!(refused.auto_hide, None);
+    assert!(
+        refused
+            .error
+            .unwrap()
+            .contains(&format!("version {}", PROTOCOL_VERSION + 1))
+    );
+
+    let _ = std::fs::remove_dir_all(&home);
+}